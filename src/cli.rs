@@ -1,12 +1,125 @@
 use clap::Parser;
 
+use crate::csv::overlay::{OverlayPreset, MAX_OVERLAY_RATE};
+use crate::csv::{CSVEncoding, CSVHeaderStyle};
+use crate::gpx::simplify::SimplifyAlgorithm;
+use crate::gpx::track_log::GPXSegmentGap;
+use crate::split::SplitPeriod;
+use crate::subtitle::{SubtitleFormat, DEFAULT_TEMPLATE};
+use crate::trip::{TripDetector, TripMode};
+use crate::zdr055::filename::{Camera, ClipFilter, ClipMode};
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum OutputFormat {
+    Gpx,
+    Kml,
+    Kmz,
+    Geojson,
+    Csv,
+    Fit,
+    Tcx,
+    Nmea,
+    Polyline,
+    Ztrk,
+}
+
+impl OutputFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Gpx => "gpx",
+            OutputFormat::Kml => "kml",
+            OutputFormat::Kmz => "kmz",
+            OutputFormat::Geojson => "geojson",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Fit => "fit",
+            OutputFormat::Tcx => "tcx",
+            OutputFormat::Nmea => "nmea",
+            OutputFormat::Polyline => "polyline",
+            OutputFormat::Ztrk => "ztrk",
+        }
+    }
+}
+
+#[derive(clap::Subcommand)]
+pub(crate) enum Command {
+    /// Stream the recorded drive as NMEA 0183 sentences (virtual GPS)
+    Replay(ReplayArgs),
+}
+
+#[derive(clap::Args)]
+pub(crate) struct ReplayArgs {
+    path: std::path::PathBuf,
+
+    /// Playback speed factor (0 sends everything without waiting)
+    #[clap(long, default_value = "1.0")]
+    speed: f64,
+
+    /// Listen on this address and send to every connected client
+    #[clap(long, conflicts_with = "udp")]
+    tcp: Option<String>,
+
+    /// Send datagrams to this address
+    #[clap(long)]
+    udp: Option<String>,
+
+    /// Skip gaps between samples longer than this instead of waiting them out
+    #[clap(long, default_value = "2m")]
+    segment_gap: humantime::Duration,
+}
+
+impl ReplayArgs {
+    pub(crate) fn get_input_path(&self) -> &std::path::PathBuf {
+        &self.path
+    }
+
+    pub(crate) fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub(crate) fn get_tcp_address(&self) -> Option<&str> {
+        self.tcp.as_deref()
+    }
+
+    pub(crate) fn get_udp_address(&self) -> Option<&str> {
+        self.udp.as_deref()
+    }
+
+    pub(crate) fn get_segment_gap(&self) -> std::time::Duration {
+        *self.segment_gap
+    }
+}
+
+fn parse_overlay_rate(s: &str) -> Result<f64, String> {
+    let rate = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if rate > 0.0 && rate <= MAX_OVERLAY_RATE {
+        Ok(rate)
+    } else {
+        Err(format!(
+            "must be greater than 0 and at most {}",
+            MAX_OVERLAY_RATE
+        ))
+    }
+}
+
+fn parse_timezone(s: &str) -> Result<chrono_tz::Tz, String> {
+    s.parse::<chrono_tz::Tz>().map_err(|e| e.to_string())
+}
+
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub(crate) struct Cli {
-    path: std::path::PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[clap(required = true)]
+    path: Option<std::path::PathBuf>,
 
     #[clap(short, long, default_value = "./")]
     output_path: Option<std::path::PathBuf>,
 
+    #[clap(short, long, value_enum, value_delimiter = ',', default_value = "gpx")]
+    format: Vec<OutputFormat>,
+
     #[clap(short, long, default_value = "8")]
     parallel: usize,
 
@@ -16,6 +129,114 @@ pub(crate) struct Cli {
     #[clap(long, default_value = "6h")]
     merge_threshold: humantime::Duration,
 
+    #[clap(long, default_value = "1000")]
+    merge_distance: f64,
+
+    #[clap(long, default_value = "150")]
+    merge_max_speed: f64,
+
+    #[clap(long, value_enum, value_delimiter = ',')]
+    camera: Vec<Camera>,
+
+    #[clap(long, value_enum, value_delimiter = ',')]
+    mode: Vec<ClipMode>,
+
+    #[clap(long, default_value = "false")]
+    keep_camera_pairs: bool,
+
+    #[clap(long, default_value = "false")]
+    verify_camera_pairs: bool,
+
+    #[clap(long, default_value = "false")]
+    include_tracks: bool,
+
+    #[clap(long, value_enum, default_value = "time")]
+    trip_mode: TripMode,
+
+    #[clap(long, value_enum)]
+    split_by: Option<SplitPeriod>,
+
+    #[clap(long, default_value = "Asia/Tokyo", value_parser = parse_timezone)]
+    timezone: chrono_tz::Tz,
+
+    #[clap(long, default_value = "15m")]
+    trip_stop: humantime::Duration,
+
+    #[clap(long, default_value = "2m")]
+    segment_gap: humantime::Duration,
+
+    #[clap(long, default_value = "1000")]
+    segment_gap_distance: f64,
+
+    #[clap(long, default_value = "false")]
+    track_per_clip: bool,
+
+    #[clap(long, default_value = "false")]
+    filter_outliers: bool,
+
+    #[clap(long, default_value = "250")]
+    max_speed: f64,
+
+    #[clap(long, default_value = "10")]
+    max_acceleration: f64,
+
+    #[clap(long, default_value = "false")]
+    smooth: bool,
+
+    #[clap(long, default_value = "5")]
+    smooth_noise: f64,
+
+    #[clap(long)]
+    interpolate_gap: Option<humantime::Duration>,
+
+    #[clap(long, value_enum)]
+    simplify: Option<SimplifyAlgorithm>,
+
+    #[clap(long, default_value = "5")]
+    simplify_tolerance: f64,
+
+    #[clap(long, default_value = "false")]
+    geojson_points: bool,
+
+    #[clap(long, default_value = ",", value_parser = parse_delimiter)]
+    csv_delimiter: char,
+
+    #[clap(long, value_enum, default_value = "field")]
+    csv_header: CSVHeaderStyle,
+
+    #[clap(long, value_enum, default_value = "utf8")]
+    csv_encoding: CSVEncoding,
+
+    #[clap(long, default_value = "5", value_parser = clap::value_parser!(u32).range(0..=9))]
+    polyline_precision: u32,
+
+    #[clap(long, default_value = "7", value_parser = clap::value_parser!(u32).range(0..=9))]
+    ztrk_precision: u32,
+
+    #[clap(long, value_enum, value_delimiter = ',')]
+    overlay_csv: Vec<OverlayPreset>,
+
+    #[clap(long, default_value = "10", value_parser = parse_overlay_rate)]
+    overlay_rate: f64,
+
+    #[clap(long)]
+    gpkg: Option<std::path::PathBuf>,
+
+    #[clap(long, default_value = "false")]
+    stats: bool,
+
+    #[clap(long)]
+    stats_json: Option<std::path::PathBuf>,
+
+    #[clap(long)]
+    dem: Option<std::path::PathBuf>,
+
+    #[clap(long, value_enum, value_delimiter = ',')]
+    subtitles: Vec<SubtitleFormat>,
+
+    #[clap(long, default_value = DEFAULT_TEMPLATE)]
+    subtitle_template: String,
+
     #[clap(short, long, default_value = "false")]
     debug: bool,
 }
@@ -28,6 +249,10 @@ impl Cli {
         }
     }
 
+    pub(crate) fn get_output_formats(&self) -> &[OutputFormat] {
+        &self.format
+    }
+
     pub(crate) fn get_parallel_count(&self) -> usize {
         if self.parallel > 0 {
             self.parallel
@@ -36,8 +261,13 @@ impl Cli {
         }
     }
 
+    pub(crate) fn get_command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
+    // サブコマンドが無いときは clap が PATH を必須にしている
     pub(crate) fn get_input_path(&self) -> &std::path::PathBuf {
-        &self.path
+        self.path.as_ref().expect("PATH is required")
     }
 
     pub(crate) fn get_merge_enabled(&self) -> bool {
@@ -48,6 +278,153 @@ impl Cli {
         self.merge_threshold
     }
 
+    pub(crate) fn get_split_period(&self) -> Option<SplitPeriod> {
+        self.split_by
+    }
+
+    pub(crate) fn get_timezone(&self) -> chrono_tz::Tz {
+        self.timezone
+    }
+
+    // クリップ間の移動として許す距離 (m)
+    pub(crate) fn get_merge_distance(&self) -> f64 {
+        self.merge_distance
+    }
+
+    // km/h
+    pub(crate) fn get_merge_max_speed(&self) -> f64 {
+        self.merge_max_speed
+    }
+
+    pub(crate) fn get_clip_filter(&self) -> ClipFilter {
+        ClipFilter::new(self.camera.clone(), self.mode.clone())
+    }
+
+    // 前後のカメラの組から前のカメラのクリップだけを読む
+    pub(crate) fn is_camera_pair_dedup_enabled(&self) -> bool {
+        !self.keep_camera_pairs
+    }
+
+    // ディレクトリ内の GPX / polyline / ztrk ファイルも入力にする
+    pub(crate) fn is_track_input_enabled(&self) -> bool {
+        self.include_tracks
+    }
+
+    pub(crate) fn is_camera_pair_verify_enabled(&self) -> bool {
+        self.verify_camera_pairs
+    }
+
+    // --trip-mode activity の場合だけ返す
+    pub(crate) fn get_trip_detector(&self) -> Option<TripDetector> {
+        match self.trip_mode {
+            TripMode::Time => None,
+            TripMode::Activity => Some(TripDetector::new(*self.trip_stop)),
+        }
+    }
+
+    pub(crate) fn get_segment_gap(&self) -> GPXSegmentGap {
+        let distance = if self.segment_gap_distance > 0.0 {
+            Some(self.segment_gap_distance)
+        } else {
+            None
+        };
+        GPXSegmentGap::new(Some(*self.segment_gap), distance)
+    }
+
+    pub(crate) fn is_track_per_clip(&self) -> bool {
+        self.track_per_clip
+    }
+
+    // 外れ値除去が有効なら (最高速度 km/h, 最大加速度 m/s^2)
+    pub(crate) fn get_outlier_limits(&self) -> Option<(f64, f64)> {
+        if self.filter_outliers {
+            Some((self.max_speed, self.max_acceleration))
+        } else {
+            None
+        }
+    }
+
+    // 平滑化が有効なら測位誤差 (m)
+    pub(crate) fn get_smooth_noise(&self) -> Option<f64> {
+        if self.smooth {
+            Some(self.smooth_noise)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn get_interpolate_gap(&self) -> Option<std::time::Duration> {
+        self.interpolate_gap.map(|gap| *gap)
+    }
+
+    pub(crate) fn get_simplify_algorithm(&self) -> Option<SimplifyAlgorithm> {
+        self.simplify
+    }
+
+    pub(crate) fn get_simplify_tolerance(&self) -> f64 {
+        self.simplify_tolerance
+    }
+
+    pub(crate) fn is_geojson_points_enabled(&self) -> bool {
+        self.geojson_points
+    }
+
+    pub(crate) fn get_csv_delimiter(&self) -> char {
+        self.csv_delimiter
+    }
+
+    pub(crate) fn get_csv_header_style(&self) -> CSVHeaderStyle {
+        self.csv_header
+    }
+
+    pub(crate) fn get_csv_encoding(&self) -> CSVEncoding {
+        self.csv_encoding
+    }
+
+    pub(crate) fn get_polyline_precision(&self) -> u32 {
+        self.polyline_precision
+    }
+
+    pub(crate) fn get_ztrk_precision(&self) -> u32 {
+        self.ztrk_precision
+    }
+
+    pub(crate) fn get_overlay_presets(&self) -> &[OverlayPreset] {
+        &self.overlay_csv
+    }
+
+    pub(crate) fn get_overlay_rate(&self) -> f64 {
+        self.overlay_rate
+    }
+
+    pub(crate) fn get_gpkg_path(&self) -> Option<&std::path::PathBuf> {
+        self.gpkg.as_ref()
+    }
+
+    pub(crate) fn is_stats_enabled(&self) -> bool {
+        self.stats || self.stats_json.is_some()
+    }
+
+    pub(crate) fn is_stats_table_enabled(&self) -> bool {
+        self.stats
+    }
+
+    pub(crate) fn get_stats_json_path(&self) -> Option<&std::path::PathBuf> {
+        self.stats_json.as_ref()
+    }
+
+    pub(crate) fn get_dem_path(&self) -> Option<&std::path::PathBuf> {
+        self.dem.as_ref()
+    }
+
+    pub(crate) fn get_subtitle_formats(&self) -> &[SubtitleFormat] {
+        &self.subtitles
+    }
+
+    pub(crate) fn get_subtitle_template(&self) -> &str {
+        &self.subtitle_template
+    }
+
     pub(crate) fn is_debug_mode(&self) -> bool {
         self.debug
     }
@@ -56,3 +433,15 @@ impl Cli {
         <Self as clap::Parser>::parse()
     }
 }
+
+// タブはシェルから渡しにくいので "tab" でも指定できるようにする
+fn parse_delimiter(s: &str) -> Result<char, String> {
+    if s.eq_ignore_ascii_case("tab") || s == "\\t" {
+        return Ok('\t');
+    }
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("Delimiter must be a single character: {}", s)),
+    }
+}
@@ -1 +1,2 @@
 pub(crate) mod avi;
+pub(crate) mod zip;
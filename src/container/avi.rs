@@ -17,10 +17,12 @@ pub(crate) struct ChunkHeader {
 }
 
 impl ChunkHeader {
+    #[allow(dead_code)]
     fn new(fourcc: [u8; 4], size: u32) -> Self {
         ChunkHeader { fourcc, size }
     }
 
+    #[allow(dead_code)]
     pub(crate) fn get_fourcc(&self) -> [u8; 4] {
         self.fourcc
     }
@@ -43,6 +45,7 @@ impl AVIIndex {
         self.chunk_id
     }
 
+    #[allow(dead_code)]
     pub(crate) fn get_flags(&self) -> u32 {
         self.flags
     }
@@ -57,8 +60,9 @@ impl AVIIndex {
 }
 
 pub(crate) trait AVI {
+    #[allow(dead_code)]
     fn open(&self, filename: &str) -> Result<Cursor<Vec<u8>>, String> {
-        let mut file = File::open(&filename).map_err(|e| format!("Failed to open file: {}", e))?;
+        let mut file = File::open(filename).map_err(|e| format!("Failed to open file: {}", e))?;
         let mut buffer = Vec::new();
         let _ = file.read_to_end(&mut buffer);
         let file_reader = std::io::Cursor::new(buffer);
@@ -0,0 +1,220 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use chrono::{Datelike, Timelike};
+use chrono_tz::Tz;
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+// 無圧縮 (stored) で格納するので展開に必要なバージョンは 1.0
+const ZIP_VERSION: u16 = 10;
+// ファイル名を UTF-8 として扱うフラグ
+const UTF8_FLAG: u16 = 1 << 11;
+
+// CRC-32 (IEEE 802.3)
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct ZIPEntry {
+    name: String,
+    data: Vec<u8>,
+    crc: u32,
+    offset: u32,
+}
+
+// KMZ 用の最小限の ZIP 書き出し。圧縮はせず stored で格納する
+pub(crate) struct ZIPArchive {
+    entries: Vec<ZIPEntry>,
+    size: usize,
+    timezone: Tz,
+}
+
+impl ZIPArchive {
+    pub(crate) fn new() -> Self {
+        ZIPArchive {
+            entries: Vec::new(),
+            size: 0,
+            timezone: chrono_tz::Asia::Tokyo,
+        }
+    }
+
+    // 更新日時はタイムゾーンを持たないローカル時刻で記録される
+    pub(crate) fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = timezone;
+    }
+
+    pub(crate) fn add_file(&mut self, name: &str, data: Vec<u8>) {
+        let crc = crc32(&data);
+        let offset = self.size as u32;
+        self.size += 30 + name.len() + data.len();
+        self.entries.push(ZIPEntry {
+            name: name.to_string(),
+            data,
+            crc,
+            offset,
+        });
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let (dos_time, dos_date) = dos_datetime(self.timezone);
+        let mut buffer = Vec::with_capacity(self.size);
+
+        for entry in self.entries.iter() {
+            buffer
+                .write_u32::<LittleEndian>(LOCAL_FILE_HEADER_SIGNATURE)
+                .unwrap();
+            buffer.write_u16::<LittleEndian>(ZIP_VERSION).unwrap();
+            buffer.write_u16::<LittleEndian>(UTF8_FLAG).unwrap();
+            buffer.write_u16::<LittleEndian>(0).unwrap(); // compression: stored
+            buffer.write_u16::<LittleEndian>(dos_time).unwrap();
+            buffer.write_u16::<LittleEndian>(dos_date).unwrap();
+            buffer.write_u32::<LittleEndian>(entry.crc).unwrap();
+            buffer
+                .write_u32::<LittleEndian>(entry.data.len() as u32)
+                .unwrap();
+            buffer
+                .write_u32::<LittleEndian>(entry.data.len() as u32)
+                .unwrap();
+            buffer
+                .write_u16::<LittleEndian>(entry.name.len() as u16)
+                .unwrap();
+            buffer.write_u16::<LittleEndian>(0).unwrap(); // extra field length
+            buffer.extend_from_slice(entry.name.as_bytes());
+            buffer.extend_from_slice(&entry.data);
+        }
+
+        let central_directory_offset = buffer.len() as u32;
+        for entry in self.entries.iter() {
+            buffer
+                .write_u32::<LittleEndian>(CENTRAL_DIRECTORY_SIGNATURE)
+                .unwrap();
+            buffer.write_u16::<LittleEndian>(ZIP_VERSION).unwrap(); // version made by
+            buffer.write_u16::<LittleEndian>(ZIP_VERSION).unwrap(); // version needed
+            buffer.write_u16::<LittleEndian>(UTF8_FLAG).unwrap();
+            buffer.write_u16::<LittleEndian>(0).unwrap();
+            buffer.write_u16::<LittleEndian>(dos_time).unwrap();
+            buffer.write_u16::<LittleEndian>(dos_date).unwrap();
+            buffer.write_u32::<LittleEndian>(entry.crc).unwrap();
+            buffer
+                .write_u32::<LittleEndian>(entry.data.len() as u32)
+                .unwrap();
+            buffer
+                .write_u32::<LittleEndian>(entry.data.len() as u32)
+                .unwrap();
+            buffer
+                .write_u16::<LittleEndian>(entry.name.len() as u16)
+                .unwrap();
+            buffer.write_u16::<LittleEndian>(0).unwrap(); // extra field length
+            buffer.write_u16::<LittleEndian>(0).unwrap(); // comment length
+            buffer.write_u16::<LittleEndian>(0).unwrap(); // disk number
+            buffer.write_u16::<LittleEndian>(0).unwrap(); // internal attributes
+            buffer.write_u32::<LittleEndian>(0).unwrap(); // external attributes
+            buffer.write_u32::<LittleEndian>(entry.offset).unwrap();
+            buffer.extend_from_slice(entry.name.as_bytes());
+        }
+        let central_directory_size = buffer.len() as u32 - central_directory_offset;
+
+        buffer
+            .write_u32::<LittleEndian>(END_OF_CENTRAL_DIRECTORY_SIGNATURE)
+            .unwrap();
+        buffer.write_u16::<LittleEndian>(0).unwrap();
+        buffer.write_u16::<LittleEndian>(0).unwrap();
+        buffer
+            .write_u16::<LittleEndian>(self.entries.len() as u16)
+            .unwrap();
+        buffer
+            .write_u16::<LittleEndian>(self.entries.len() as u16)
+            .unwrap();
+        buffer
+            .write_u32::<LittleEndian>(central_directory_size)
+            .unwrap();
+        buffer
+            .write_u32::<LittleEndian>(central_directory_offset)
+            .unwrap();
+        buffer.write_u16::<LittleEndian>(0).unwrap(); // comment length
+        buffer
+    }
+}
+
+// 現在時刻を MS-DOS 形式の (時刻, 日付) にする
+fn dos_datetime(timezone: Tz) -> (u16, u16) {
+    let now = chrono::Utc::now().with_timezone(&timezone);
+    let time = (now.hour() << 11 | now.minute() << 5 | (now.second() / 2)) as u16;
+    let date = (((now.year() - 1980).max(0) as u32) << 9 | now.month() << 5 | now.day()) as u16;
+    (time, date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::ByteOrder;
+
+    #[test]
+    fn computes_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn writes_zip_records() {
+        let mut archive = ZIPArchive::new();
+        archive.add_file("doc.kml", b"<kml/>".to_vec());
+        archive.add_file("files/a.txt", b"abc".to_vec());
+        let bytes = archive.to_bytes();
+        let u16_at = |offset: usize| LittleEndian::read_u16(&bytes[offset..]);
+        let u32_at = |offset: usize| LittleEndian::read_u32(&bytes[offset..]);
+
+        // ローカルファイルヘッダーとデータ
+        assert_eq!(u32_at(0), LOCAL_FILE_HEADER_SIGNATURE);
+        assert_eq!(u16_at(6), UTF8_FLAG);
+        assert_eq!(u16_at(8), 0);
+        assert_eq!(u32_at(14), crc32(b"<kml/>"));
+        assert_eq!(u32_at(18), 6);
+        assert_eq!(u32_at(22), 6);
+        assert_eq!(u16_at(26), 7);
+        assert_eq!(&bytes[30..37], b"doc.kml");
+        assert_eq!(&bytes[37..43], b"<kml/>");
+        let second = 43;
+        assert_eq!(u32_at(second), LOCAL_FILE_HEADER_SIGNATURE);
+        assert_eq!(&bytes[second + 30..second + 41], b"files/a.txt");
+        assert_eq!(&bytes[second + 41..second + 44], b"abc");
+
+        // 末尾の EOCD から中央ディレクトリをたどる
+        let eocd = bytes.len() - 22;
+        assert_eq!(u32_at(eocd), END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        assert_eq!(u16_at(eocd + 8), 2);
+        assert_eq!(u16_at(eocd + 10), 2);
+        let central_directory_offset = u32_at(eocd + 16) as usize;
+        assert_eq!(central_directory_offset, second + 44);
+        assert_eq!(u32_at(eocd + 12) as usize, eocd - central_directory_offset);
+
+        let mut offset = central_directory_offset;
+        for (name, data, local_offset) in [
+            ("doc.kml", &b"<kml/>"[..], 0),
+            ("files/a.txt", &b"abc"[..], second),
+        ] {
+            assert_eq!(u32_at(offset), CENTRAL_DIRECTORY_SIGNATURE);
+            assert_eq!(u32_at(offset + 16), crc32(data));
+            assert_eq!(u32_at(offset + 20) as usize, data.len());
+            assert_eq!(u16_at(offset + 28) as usize, name.len());
+            assert_eq!(u32_at(offset + 42) as usize, local_offset);
+            assert_eq!(
+                &bytes[offset + 46..offset + 46 + name.len()],
+                name.as_bytes()
+            );
+            offset += 46 + name.len();
+        }
+        assert_eq!(offset, eocd);
+    }
+}
@@ -0,0 +1,241 @@
+pub(crate) mod overlay;
+
+use encoding_rs::SHIFT_JIS;
+
+use crate::zdr055::ZDR055PositionData;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum CSVHeaderStyle {
+    // ZDR055PositionData のフィールド名そのまま
+    Field,
+    // 単位付きの英語ラベル
+    Label,
+    // 単位付きの日本語ラベル
+    Japanese,
+    None,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum CSVEncoding {
+    Utf8,
+    // 日本語版 Excel で文字化けしないよう BOM を付ける
+    Utf8Bom,
+    ShiftJis,
+}
+
+fn label(field: &str, style: CSVHeaderStyle) -> &str {
+    match style {
+        CSVHeaderStyle::Label => match field {
+            "source" => "Source file",
+            "index" => "Index",
+            "device" => "Device",
+            "timestamp" => "Timestamp",
+            "x_accel" => "X acceleration (G)",
+            "y_accel" => "Y acceleration (G)",
+            "z_accel" => "Z acceleration (G)",
+            "unknown_field_t" => "Unknown T",
+            "supply_voltage" => "Supply voltage (V)",
+            "event_type" => "Event type",
+            "latitude" => "Latitude",
+            "longitude" => "Longitude",
+            "speed" => "Speed (km/h)",
+            "e_value" => "E",
+            "m_value" => "M",
+            "em_value" => "EM",
+            "sa_value" => "SA",
+            "firmware_version" => "Firmware version",
+            "s_value" => "S (k)",
+            "unknown_field_tail" => "Unknown tail",
+            "is_valid" => "Valid",
+            _ => field,
+        },
+        CSVHeaderStyle::Japanese => match field {
+            "source" => "ファイル",
+            "index" => "インデックス",
+            "device" => "機種",
+            "timestamp" => "日時",
+            "x_accel" => "X加速度 (G)",
+            "y_accel" => "Y加速度 (G)",
+            "z_accel" => "Z加速度 (G)",
+            "unknown_field_t" => "不明 (T)",
+            "supply_voltage" => "電源電圧 (V)",
+            "event_type" => "イベント種別",
+            "latitude" => "緯度",
+            "longitude" => "経度",
+            "speed" => "速度 (km/h)",
+            "e_value" => "E",
+            "m_value" => "M",
+            "em_value" => "EM",
+            "sa_value" => "SA",
+            "firmware_version" => "ファームウェアバージョン",
+            "s_value" => "S (k)",
+            "unknown_field_tail" => "不明 (末尾)",
+            "is_valid" => "有効",
+            _ => field,
+        },
+        CSVHeaderStyle::Field | CSVHeaderStyle::None => field,
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CSV {
+    // (元ファイル名, ストリーム内のインデックス, ログデータ)
+    rows: Vec<(String, usize, ZDR055PositionData)>,
+    delimiter: char,
+    header_style: CSVHeaderStyle,
+    encoding: CSVEncoding,
+}
+
+impl CSV {
+    pub(crate) fn new(rows: Vec<(String, usize, ZDR055PositionData)>) -> Self {
+        CSV {
+            rows,
+            delimiter: ',',
+            header_style: CSVHeaderStyle::Field,
+            encoding: CSVEncoding::Utf8,
+        }
+    }
+
+    pub(crate) fn set_delimiter(&mut self, delimiter: char) {
+        self.delimiter = delimiter;
+    }
+
+    pub(crate) fn set_header_style(&mut self, header_style: CSVHeaderStyle) {
+        self.header_style = header_style;
+    }
+
+    pub(crate) fn set_encoding(&mut self, encoding: CSVEncoding) {
+        self.encoding = encoding;
+    }
+
+    fn escape(&self, value: &str) -> String {
+        if value.contains(self.delimiter)
+            || value.contains('"')
+            || value.contains('\n')
+            || value.contains('\r')
+        {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    fn join(&self, values: &[String]) -> String {
+        values
+            .iter()
+            .map(|value| self.escape(value))
+            .collect::<Vec<String>>()
+            .join(&self.delimiter.to_string())
+    }
+
+    pub(crate) fn to_str(&self) -> String {
+        let mut lines = Vec::new();
+        if self.header_style != CSVHeaderStyle::None {
+            let mut header = vec![
+                label("source", self.header_style).to_string(),
+                label("index", self.header_style).to_string(),
+            ];
+            header.extend(
+                ZDR055PositionData::default()
+                    .fields()
+                    .iter()
+                    .map(|(field, _)| label(field, self.header_style).to_string()),
+            );
+            lines.push(self.join(&header));
+        }
+        for (source, index, data) in self.rows.iter() {
+            let mut values = vec![source.clone(), index.to_string()];
+            values.extend(data.fields().into_iter().map(|(_, value)| value));
+            lines.push(self.join(&values));
+        }
+        // Excel で開くことを考えて改行は CRLF にする
+        lines.join("\r\n") + "\r\n"
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let csv_string = self.to_str();
+        match self.encoding {
+            CSVEncoding::Utf8 => csv_string.into_bytes(),
+            CSVEncoding::Utf8Bom => {
+                let mut bytes = vec![0xef, 0xbb, 0xbf];
+                bytes.extend(csv_string.into_bytes());
+                bytes
+            }
+            CSVEncoding::ShiftJis => {
+                let (bytes, _, had_errors) = SHIFT_JIS.encode(&csv_string);
+                if had_errors {
+                    eprintln!("Some characters could not be encoded in Shift_JIS");
+                }
+                bytes.into_owned()
+            }
+        }
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|e| format!("Failed to write CSV file: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_csv(source: &str) -> CSV {
+        CSV::new(vec![(source.to_string(), 3, ZDR055PositionData::default())])
+    }
+
+    #[test]
+    fn keeps_decimal_places() {
+        let data = "ZDR055:2025-07-20 12:48:12 X:0.10 Y:-0.02 Z:1.00 T:0 12.0V N 35.680000 N 139.760000 E 40.0km/h E:0 M:1 EM:0 SA:2 V:13 S:120k 0A"
+            .parse::<ZDR055PositionData>()
+            .unwrap();
+        let mut csv = CSV::new(vec![("clip.AVI".to_string(), 0, data)]);
+        csv.set_header_style(CSVHeaderStyle::None);
+        assert!(csv.to_str().starts_with(
+            "clip.AVI,0,ZDR055,2025-07-20 12:48:12,0.10,-0.02,1.00,T:0,12.0,N,35.680000,139.760000,40.0,"
+        ));
+    }
+
+    #[test]
+    fn escapes_values() {
+        let mut csv = new_csv("a,b \"c\"\nd");
+        csv.set_header_style(CSVHeaderStyle::None);
+        let s = csv.to_str();
+        assert!(s.starts_with("\"a,b \"\"c\"\"\nd\",3,"));
+        assert!(s.ends_with("\r\n"));
+        assert_eq!(s.matches("\r\n").count(), 1);
+
+        // 区切り文字を変えるとカンマは囲まない
+        let mut csv = new_csv("a,b");
+        csv.set_header_style(CSVHeaderStyle::None);
+        csv.set_delimiter('\t');
+        assert!(csv.to_str().starts_with("a,b\t3\t"));
+    }
+
+    #[test]
+    fn encodes_output() {
+        let mut csv = new_csv("clip.AVI");
+        csv.set_header_style(CSVHeaderStyle::Japanese);
+        let s = csv.to_str();
+        assert!(s.starts_with("ファイル,インデックス,機種,日時,"));
+
+        assert_eq!(csv.to_bytes(), s.as_bytes());
+
+        csv.set_encoding(CSVEncoding::Utf8Bom);
+        let bytes = csv.to_bytes();
+        assert_eq!(&bytes[..3], &[0xef, 0xbb, 0xbf]);
+        assert_eq!(&bytes[3..], s.as_bytes());
+
+        csv.set_encoding(CSVEncoding::ShiftJis);
+        let bytes = csv.to_bytes();
+        // "ファイル"
+        assert_eq!(
+            &bytes[..8],
+            &[0x83, 0x74, 0x83, 0x40, 0x83, 0x43, 0x83, 0x8b]
+        );
+        let (decoded, _, had_errors) = SHIFT_JIS.decode(&bytes);
+        assert!(!had_errors);
+        assert_eq!(decoded, s);
+    }
+}
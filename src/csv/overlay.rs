@@ -0,0 +1,296 @@
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+
+use crate::gpx::track_point::GPXTrackPoint;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum OverlayPreset {
+    Dashware,
+    TelemetryOverlay,
+}
+
+impl OverlayPreset {
+    // 出力ファイル名の末尾に付ける
+    pub(crate) fn suffix(&self) -> &'static str {
+        match self {
+            OverlayPreset::Dashware => "dashware",
+            OverlayPreset::TelemetryOverlay => "telemetry_overlay",
+        }
+    }
+
+    fn header(&self) -> &'static str {
+        match self {
+            OverlayPreset::Dashware => "Time (s),Date Time,Latitude,Longitude,Altitude (m),Speed (km/h),Lateral G,Longitudinal G,Vertical G,Voltage (V)",
+            OverlayPreset::TelemetryOverlay => "cts (ms),utc (ms),lat (deg),lon (deg),alt (m),speed2d (m/s),accl_x (m/s2),accl_y (m/s2),accl_z (m/s2)",
+        }
+    }
+}
+
+// 1秒あたりの行数の上限。 1 ms ごと
+pub(crate) const MAX_OVERLAY_RATE: f64 = 1000.0;
+
+// 標準重力加速度 (m/s^2)
+const STANDARD_GRAVITY: f64 = 9.80665;
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn lerp_option(a: Option<f64>, b: Option<f64>, t: f64) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(lerp(a, b, t)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn format_option(value: Option<f64>, precision: usize) -> String {
+    value
+        .map(|value| format!("{:.*}", precision, value))
+        .unwrap_or_default()
+}
+
+// 補間した1行分の値
+struct OverlayRow {
+    offset: Duration,
+    time: DateTime<Tz>,
+    lat: f64,
+    lon: f64,
+    ele: Option<f64>,
+    speed: f64,
+    acceleration: Option<[f64; 3]>,
+    voltage: Option<f64>,
+}
+
+impl OverlayRow {
+    fn to_csv_line(&self, preset: OverlayPreset) -> String {
+        let [x, y, z] = self.acceleration.unwrap_or_default();
+        match preset {
+            OverlayPreset::Dashware => format!(
+                "{:.3},{},{:.7},{:.7},{},{:.2},{:.3},{:.3},{:.3},{}",
+                self.offset.num_milliseconds() as f64 / 1000.0,
+                self.time.format("%Y-%m-%d %H:%M:%S%.3f"),
+                self.lat,
+                self.lon,
+                format_option(self.ele, 2),
+                self.speed,
+                x,
+                y,
+                z,
+                format_option(self.voltage, 2)
+            ),
+            OverlayPreset::TelemetryOverlay => format!(
+                "{},{},{:.7},{:.7},{},{:.3},{:.3},{:.3},{:.3}",
+                self.offset.num_milliseconds(),
+                self.time.timestamp_millis(),
+                self.lat,
+                self.lon,
+                format_option(self.ele, 2),
+                self.speed / 3.6,
+                x * STANDARD_GRAVITY,
+                y * STANDARD_GRAVITY,
+                z * STANDARD_GRAVITY
+            ),
+        }
+    }
+}
+
+// 1 Hz のログを動画編集ソフトが読める間隔に補間して書き出す
+#[derive(Debug)]
+pub(crate) struct OverlayCSV {
+    points: Vec<GPXTrackPoint>,
+    rate: f64,
+    // 動画の先頭の時刻。分からなければ先頭のサンプルの時刻
+    start: Option<NaiveDateTime>,
+}
+
+impl OverlayCSV {
+    pub(crate) fn new(points: Vec<GPXTrackPoint>) -> Self {
+        OverlayCSV {
+            points,
+            rate: 10.0,
+            start: None,
+        }
+    }
+
+    pub(crate) fn set_start(&mut self, start: NaiveDateTime) {
+        self.start = Some(start);
+    }
+
+    // 1秒あたりの行数
+    pub(crate) fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+    }
+
+    // 動画の先頭を 0 秒とし、 1 / rate 秒ごとに前後のサンプルから線形補間する。
+    // 最初のサンプルより前と最後のサンプルより後の行は書かない
+    fn rows(&self) -> Vec<OverlayRow> {
+        let points = self
+            .points
+            .iter()
+            .filter_map(|point| point.timestamp().ok().map(|time| (point, time)))
+            .collect::<Vec<_>>();
+        let (start, end) = match (points.first(), points.last()) {
+            (Some((_, start)), Some((_, end))) => (*start, *end),
+            _ => return Vec::new(),
+        };
+        let origin = self
+            .start
+            .and_then(|clip_start| start.timezone().from_local_datetime(&clip_start).earliest())
+            .unwrap_or(start);
+        let first_ms = (start - origin).num_milliseconds().max(0);
+        let last_ms = (end - origin).num_milliseconds();
+        let step_ms = if self.rate > 0.0 {
+            1000.0 / self.rate.min(MAX_OVERLAY_RATE)
+        } else {
+            1000.0
+        };
+
+        let mut rows = Vec::new();
+        let mut i = 0;
+        let mut k = (first_ms as f64 / step_ms).ceil() as i64;
+        loop {
+            let offset_ms = (k as f64 * step_ms).round() as i64;
+            if offset_ms > last_ms {
+                break;
+            }
+            let offset = Duration::milliseconds(offset_ms);
+            let time = origin + offset;
+            while i + 1 < points.len() && points[i + 1].1 <= time {
+                i += 1;
+            }
+            let (a, a_time) = points[i];
+            let (b, t) = match points.get(i + 1) {
+                Some((b, b_time)) if *b_time > a_time => (
+                    *b,
+                    ((time - a_time).num_milliseconds() as f64
+                        / (*b_time - a_time).num_milliseconds() as f64)
+                        .max(0.0),
+                ),
+                _ => (a, 0.0),
+            };
+            let acceleration = match (a.acceleration(), b.acceleration()) {
+                (Some(a), Some(b)) => Some([
+                    lerp(a[0], b[0], t),
+                    lerp(a[1], b[1], t),
+                    lerp(a[2], b[2], t),
+                ]),
+                (a, b) => a.or(b),
+            };
+            rows.push(OverlayRow {
+                offset,
+                time,
+                lat: lerp(a.lat(), b.lat(), t),
+                lon: lerp(a.lon(), b.lon(), t),
+                ele: lerp_option(a.ele(), b.ele(), t),
+                speed: lerp(a.speed(), b.speed(), t),
+                acceleration,
+                voltage: lerp_option(a.voltage(), b.voltage(), t),
+            });
+            k += 1;
+        }
+        rows
+    }
+
+    pub(crate) fn to_str(&self, preset: OverlayPreset) -> String {
+        let mut lines = vec![preset.header().to_string()];
+        lines.extend(self.rows().iter().map(|row| row.to_csv_line(preset)));
+        lines.join("\r\n") + "\r\n"
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path, preset: OverlayPreset) -> Result<(), String> {
+        std::fs::write(path, self.to_str(preset))
+            .map_err(|e| format!("Failed to write CSV file: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay(rate: f64) -> OverlayCSV {
+        let mut first = GPXTrackPoint::new(
+            35.0,
+            139.0,
+            Some(10.0),
+            36.0,
+            "2025-07-20 12:48:10".to_string(),
+        );
+        first.set_acceleration(Some([0.0, 0.2, 1.0]));
+        let mut second = GPXTrackPoint::new(
+            35.001,
+            139.002,
+            Some(20.0),
+            72.0,
+            "2025-07-20 12:48:11".to_string(),
+        );
+        second.set_acceleration(Some([0.4, 0.0, 1.0]));
+        let third = GPXTrackPoint::new(
+            35.002,
+            139.004,
+            None,
+            72.0,
+            "2025-07-20 12:48:12".to_string(),
+        );
+        let mut overlay = OverlayCSV::new(vec![first, second, third]);
+        overlay.set_rate(rate);
+        overlay
+    }
+
+    #[test]
+    fn emits_rows_at_rate() {
+        assert_eq!(overlay(10.0).rows().len(), 21);
+        assert_eq!(overlay(1.0).rows().len(), 3);
+        let offsets = overlay(3.0)
+            .rows()
+            .iter()
+            .map(|row| row.offset.num_milliseconds())
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 333, 667, 1000, 1333, 1667, 2000]);
+        // 上限を超える値は上限として扱う
+        assert_eq!(overlay(f64::INFINITY).rows().len(), 2001);
+    }
+
+    #[test]
+    fn anchors_rows_to_clip_start() {
+        // GPS の測位が録画開始から 2.5 秒遅れた場合
+        let mut overlay = overlay(2.0);
+        overlay.set_start(
+            NaiveDateTime::parse_from_str("2025-07-20 12:48:07.500", "%Y-%m-%d %H:%M:%S%.3f")
+                .unwrap(),
+        );
+        let rows = overlay.rows();
+        let offsets = rows
+            .iter()
+            .map(|row| row.offset.num_milliseconds())
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![2500, 3000, 3500, 4000, 4500]);
+        assert_eq!(
+            rows[0].time.format("%H:%M:%S%.3f").to_string(),
+            "12:48:10.000"
+        );
+        assert!((rows[1].lat - 35.0005).abs() < 1e-9);
+        assert!(overlay
+            .to_str(OverlayPreset::Dashware)
+            .contains("\r\n2.500,2025-07-20 12:48:10.000,"));
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let rows = overlay(4.0).rows();
+        let row = &rows[2];
+        assert_eq!(row.offset.num_milliseconds(), 500);
+        assert_eq!(row.time.format("%H:%M:%S%.3f").to_string(), "12:48:10.500");
+        assert!((row.lat - 35.0005).abs() < 1e-9);
+        assert!((row.lon - 139.001).abs() < 1e-9);
+        assert_eq!(row.ele, Some(15.0));
+        assert!((row.speed - 54.0).abs() < 1e-9);
+        let [x, y, _] = row.acceleration.unwrap();
+        assert!((x - 0.2).abs() < 1e-9 && (y - 0.1).abs() < 1e-9);
+
+        // 片方にしか無い値は補間せずにその値を使う
+        let row = &rows[6];
+        assert_eq!(row.offset.num_milliseconds(), 1500);
+        assert_eq!(row.ele, Some(20.0));
+        assert_eq!(row.acceleration, Some([0.4, 0.0, 1.0]));
+    }
+}
@@ -0,0 +1,138 @@
+pub(crate) mod gsi;
+pub(crate) mod srtm;
+
+use std::collections::HashMap;
+use std::path;
+
+// 国土地理院の標高タイルは dem5a (z15) を優先し、無ければ dem10b (z14) を使う
+const GSI_ZOOM_LEVELS: [u32; 2] = [15, 14];
+
+// 正方形のグリッドに並んだ標高値。欠測値は None
+#[derive(Debug)]
+pub(crate) struct DEMTile {
+    size: usize,
+    samples: Vec<Option<f64>>,
+}
+
+impl DEMTile {
+    pub(crate) fn new(size: usize, samples: Vec<Option<f64>>) -> Result<Self, String> {
+        if samples.len() != size * size {
+            return Err(format!(
+                "Invalid DEM tile size: expected {} samples, got {}",
+                size * size,
+                samples.len()
+            ));
+        }
+        Ok(DEMTile { size, samples })
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn get(&self, row: usize, col: usize) -> Option<f64> {
+        if row >= self.size || col >= self.size {
+            return None;
+        }
+        self.samples[row * self.size + col]
+    }
+}
+
+// ユーザーが指定したディレクトリ内の SRTM (.hgt) / 国土地理院標高タイル (.txt) から標高を求める
+pub(crate) struct DEM {
+    dir: path::PathBuf,
+    tiles: HashMap<path::PathBuf, Option<DEMTile>>,
+}
+
+impl DEM {
+    pub(crate) fn new(dir: &path::Path) -> Self {
+        DEM {
+            dir: dir.to_path_buf(),
+            tiles: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn elevation(&mut self, lat: f64, lon: f64) -> Option<f64> {
+        if let Some(ele) = srtm::elevation(self, lat, lon) {
+            return Some(ele);
+        }
+        GSI_ZOOM_LEVELS
+            .iter()
+            .find_map(|zoom| gsi::elevation(self, lat, lon, *zoom))
+    }
+
+    // タイルを読み込んでキャッシュする。ファイルが無い場合や読み込めない場合は None
+    fn tile(
+        &mut self,
+        relative_path: &path::Path,
+        loader: fn(&path::Path) -> Result<DEMTile, String>,
+    ) -> Option<&DEMTile> {
+        let path = self.dir.join(relative_path);
+        self.tiles
+            .entry(path)
+            .or_insert_with_key(|path| {
+                if !path.is_file() {
+                    return None;
+                }
+                loader(path)
+                    .map_err(|e| eprintln!("Failed to load DEM tile {}: {}", path.display(), e))
+                    .ok()
+            })
+            .as_ref()
+    }
+}
+
+// 4隅 (左上, 右上, 左下, 右下) の値から双線形補間する。欠測している隅は除外して重みを正規化する
+fn bilinear(corners: [Option<f64>; 4], fx: f64, fy: f64) -> Option<f64> {
+    let weights = [
+        (1.0 - fx) * (1.0 - fy),
+        fx * (1.0 - fy),
+        (1.0 - fx) * fy,
+        fx * fy,
+    ];
+    let mut sum = 0.0;
+    let mut weight_sum = 0.0;
+    for (value, weight) in corners.iter().zip(weights.iter()) {
+        if let Some(value) = value {
+            sum += value * weight;
+            weight_sum += weight;
+        }
+    }
+    if weight_sum > 0.0 {
+        Some(sum / weight_sum)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_bilinearly() {
+        let corners = [Some(100.0), Some(200.0), Some(300.0), Some(400.0)];
+        assert_eq!(bilinear(corners, 0.0, 0.0), Some(100.0));
+        assert_eq!(bilinear(corners, 1.0, 0.0), Some(200.0));
+        assert_eq!(bilinear(corners, 0.0, 1.0), Some(300.0));
+        assert_eq!(bilinear(corners, 0.5, 0.5), Some(250.0));
+        assert_eq!(bilinear(corners, 0.25, 0.0), Some(125.0));
+    }
+
+    #[test]
+    fn skips_missing_corners() {
+        // 欠測している隅を除いた重みで正規化される
+        let corners = [Some(100.0), None, Some(300.0), None];
+        assert_eq!(bilinear(corners, 0.5, 0.5), Some(200.0));
+        assert_eq!(bilinear([None; 4], 0.5, 0.5), None);
+    }
+
+    #[test]
+    fn checks_tile_size() {
+        assert!(DEMTile::new(2, vec![Some(1.0); 3]).is_err());
+        let tile = DEMTile::new(2, vec![Some(1.0), Some(2.0), Some(3.0), None]).unwrap();
+        assert_eq!(tile.get(1, 0), Some(3.0));
+        assert_eq!(tile.get(1, 1), None);
+        assert_eq!(tile.get(2, 0), None);
+    }
+}
@@ -0,0 +1,143 @@
+use std::f64::consts::PI;
+use std::path;
+
+use super::{bilinear, DEMTile, DEM};
+
+// 国土地理院の標高タイルは 256x256 ピクセル
+const TILE_SIZE: usize = 256;
+
+// {z}/{x}/{y}.txt のテキスト形式。カンマ区切りで 256 行、欠測は "e"
+fn load(path: &path::Path) -> Result<DEMTile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let mut samples = Vec::with_capacity(TILE_SIZE * TILE_SIZE);
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        for value in line.split(',') {
+            let value = value.trim();
+            if value == "e" {
+                samples.push(None);
+            } else {
+                let ele = value
+                    .parse::<f64>()
+                    .map_err(|_| format!("Failed to parse elevation: {}", value))?;
+                samples.push(Some(ele));
+            }
+        }
+    }
+    DEMTile::new(TILE_SIZE, samples)
+}
+
+// 指定したズームレベルでの全体ピクセル座標 (Web メルカトル)
+fn pixel_position(lat: f64, lon: f64, zoom: u32) -> (f64, f64) {
+    let world_size = (TILE_SIZE as f64) * 2f64.powi(zoom as i32);
+    let x = (lon + 180.0) / 360.0 * world_size;
+    let lat_rad = lat.to_radians();
+    let y = (1.0 - (lat_rad.tan() + 1.0 / lat_rad.cos()).ln() / PI) / 2.0 * world_size;
+    (x, y)
+}
+
+fn sample(dem: &mut DEM, zoom: u32, px: i64, py: i64) -> Option<f64> {
+    if px < 0 || py < 0 {
+        return None;
+    }
+    let size = TILE_SIZE as i64;
+    let relative_path = path::PathBuf::from(format!("{}/{}/{}.txt", zoom, px / size, py / size));
+    let tile = dem.tile(&relative_path, load)?;
+    tile.get((py % size) as usize, (px % size) as usize)
+}
+
+pub(crate) fn elevation(dem: &mut DEM, lat: f64, lon: f64, zoom: u32) -> Option<f64> {
+    let (x, y) = pixel_position(lat, lon, zoom);
+    // 値はピクセル中心のものなので半ピクセルずらして補間する
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (px, py) = (x.floor() as i64, y.floor() as i64);
+    bilinear(
+        [
+            sample(dem, zoom, px, py),
+            sample(dem, zoom, px + 1, py),
+            sample(dem, zoom, px, py + 1),
+            sample(dem, zoom, px + 1, py + 1),
+        ],
+        x - x.floor(),
+        y - y.floor(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 各ピクセルの値が 行 * 1000 + 列 になるタイルを書き出す
+    fn write_tile(dir: &path::Path, relative_path: &str, void: Option<(usize, usize)>) {
+        let path = dir.join(relative_path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let text = (0..TILE_SIZE)
+            .map(|row| {
+                (0..TILE_SIZE)
+                    .map(|col| {
+                        if void == Some((row, col)) {
+                            "e".to_string()
+                        } else {
+                            (row * 1000 + col).to_string()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        std::fs::write(path, text).unwrap();
+    }
+
+    // ズームレベル zoom での全体ピクセル座標から緯度経度を求める
+    fn position(x: f64, y: f64, zoom: u32) -> (f64, f64) {
+        let world_size = (TILE_SIZE as f64) * 2f64.powi(zoom as i32);
+        let lon = x / world_size * 360.0 - 180.0;
+        let lat = (PI * (1.0 - 2.0 * y / world_size))
+            .sinh()
+            .atan()
+            .to_degrees();
+        (lat, lon)
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn computes_pixel_position() {
+        let (x, y) = pixel_position(0.0, 0.0, 0);
+        assert!((x - 128.0).abs() < 1e-9 && (y - 128.0).abs() < 1e-9);
+        let (x, y) = pixel_position(0.0, 90.0, 2);
+        assert!((x - 768.0).abs() < 1e-9 && (y - 512.0).abs() < 1e-9);
+        let (lat, lon) = position(300.5, 10.5, 1);
+        let (x, y) = pixel_position(lat, lon, 1);
+        assert!((x - 300.5).abs() < 1e-6 && (y - 10.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reads_pixel_centers_from_tiles() {
+        let dir = std::env::temp_dir().join(format!("zdr055_gpx_gsi_{}", std::process::id()));
+        write_tile(&dir, "1/1/0.txt", Some((20, 45)));
+        let mut dem = DEM::new(&dir);
+
+        // 全体ピクセル x=300 はタイル x=1 の 44 列目
+        let (lat, lon) = position(300.5, 10.5, 1);
+        assert_close(elevation(&mut dem, lat, lon, 1), 10044.0);
+        let (lat, lon) = position(301.0, 11.0, 1);
+        assert_close(elevation(&mut dem, lat, lon, 1), 10544.5);
+
+        // 欠測ピクセルは補間から除外される
+        let (lat, lon) = position(301.0, 20.5, 1);
+        assert_close(elevation(&mut dem, lat, lon, 1), 20044.0);
+
+        // 読み込んでいないタイルの範囲は None
+        let (lat, lon) = position(100.5, 10.5, 1);
+        assert_eq!(elevation(&mut dem, lat, lon, 1), None);
+    }
+}
@@ -0,0 +1,106 @@
+use std::path;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use super::{bilinear, DEMTile, DEM};
+
+// SRTM の欠測値
+const VOID_VALUE: i16 = -32768;
+
+// N35E139.hgt のようなファイル名。タイルは南西端の整数緯度経度で命名される
+fn tile_name(lat: f64, lon: f64) -> String {
+    let lat_floor = lat.floor() as i32;
+    let lon_floor = lon.floor() as i32;
+    format!(
+        "{}{:02}{}{:03}.hgt",
+        if lat_floor >= 0 { 'N' } else { 'S' },
+        lat_floor.abs(),
+        if lon_floor >= 0 { 'E' } else { 'W' },
+        lon_floor.abs()
+    )
+}
+
+// 1201x1201 (3秒) または 3601x3601 (1秒) の big endian i16 配列
+fn load(path: &path::Path) -> Result<DEMTile, String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let size = ((data.len() / 2) as f64).sqrt() as usize;
+    if size < 2 || size * size * 2 != data.len() {
+        return Err(format!("Unexpected file size: {}", data.len()));
+    }
+    let samples = data
+        .chunks_exact(2)
+        .map(|v| match BigEndian::read_i16(v) {
+            VOID_VALUE => None,
+            v => Some(v as f64),
+        })
+        .collect();
+    DEMTile::new(size, samples)
+}
+
+pub(crate) fn elevation(dem: &mut DEM, lat: f64, lon: f64) -> Option<f64> {
+    let name = tile_name(lat, lon);
+    let tile = dem.tile(path::Path::new(&name), load)?;
+
+    // 1行目が北端、各行の先頭が西端。端の行・列は隣のタイルと重複している
+    let cells = (tile.size() - 1) as f64;
+    let y = (lat.floor() + 1.0 - lat) * cells;
+    let x = (lon - lon.floor()) * cells;
+    let (row, col) = (y.floor() as usize, x.floor() as usize);
+    bilinear(
+        [
+            tile.get(row, col),
+            tile.get(row, col + 1),
+            tile.get(row + 1, col),
+            tile.get(row + 1, col + 1),
+        ],
+        x - x.floor(),
+        y - y.floor(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 北から順に 100..900 が並ぶ 3x3 のタイルを一時ディレクトリに作る
+    fn dem(name: &str, void: Option<usize>) -> DEM {
+        let dir =
+            std::env::temp_dir().join(format!("zdr055_gpx_srtm_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut data = vec![0u8; 18];
+        for i in 0..9 {
+            let value = if void == Some(i) {
+                VOID_VALUE
+            } else {
+                (i as i16 + 1) * 100
+            };
+            BigEndian::write_i16(&mut data[i * 2..], value);
+        }
+        std::fs::write(dir.join("N35E139.hgt"), data).unwrap();
+        DEM::new(&dir)
+    }
+
+    #[test]
+    fn names_tiles_by_south_west_corner() {
+        assert_eq!(tile_name(35.6, 139.7), "N35E139.hgt");
+        assert_eq!(tile_name(-33.5, -70.2), "S34W071.hgt");
+        assert_eq!(tile_name(0.5, 5.5), "N00E005.hgt");
+    }
+
+    #[test]
+    fn indexes_rows_from_north() {
+        let mut dem = dem("rows", None);
+        assert_eq!(elevation(&mut dem, 35.5, 139.0), Some(400.0));
+        assert_eq!(elevation(&mut dem, 35.5, 139.5), Some(500.0));
+        assert_eq!(elevation(&mut dem, 35.25, 139.5), Some(650.0));
+        assert_eq!(elevation(&mut dem, 35.75, 139.25), Some(300.0));
+        assert_eq!(elevation(&mut dem, 34.5, 139.5), None);
+    }
+
+    #[test]
+    fn ignores_void_samples() {
+        let mut dem = dem("void", Some(1));
+        let ele = elevation(&mut dem, 35.75, 139.25).unwrap();
+        assert!((ele - 1000.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,552 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use chrono::{DateTime, Offset};
+use chrono_tz::Tz;
+
+use crate::gpx::track_log::{GPXSegmentGap, GPXTrackLog};
+use crate::gpx::track_point::GPXTrackPoint;
+
+const HEADER_SIZE: u8 = 14;
+const PROTOCOL_VERSION: u8 = 0x20;
+const PROFILE_VERSION: u16 = 2132;
+// FIT の時刻は 1989-12-31T00:00:00Z からの秒数
+const FIT_EPOCH_OFFSET: i64 = 631065600;
+
+// グローバルメッセージ番号
+const MESG_FILE_ID: u16 = 0;
+const MESG_SESSION: u16 = 18;
+const MESG_LAP: u16 = 19;
+const MESG_RECORD: u16 = 20;
+const MESG_EVENT: u16 = 21;
+const MESG_ACTIVITY: u16 = 34;
+
+// 列挙値
+const FILE_ACTIVITY: u8 = 4;
+const MANUFACTURER_DEVELOPMENT: u16 = 255;
+const SPORT_DRIVING: u8 = 24;
+const EVENT_TIMER: u8 = 0;
+const EVENT_LAP: u8 = 9;
+const EVENT_SESSION: u8 = 8;
+const EVENT_ACTIVITY: u8 = 26;
+const EVENT_TYPE_START: u8 = 0;
+const EVENT_TYPE_STOP: u8 = 1;
+const EVENT_TYPE_STOP_ALL: u8 = 4;
+
+// FIT SDK の CRC-16 (CRC-16/ARC と同じもの)
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800, 0xB401,
+    0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+pub(crate) fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        let tmp = CRC_TABLE[(crc & 0xF) as usize];
+        crc = (crc >> 4) & 0x0FFF;
+        crc = crc ^ tmp ^ CRC_TABLE[(byte & 0xF) as usize];
+        let tmp = CRC_TABLE[(crc & 0xF) as usize];
+        crc = (crc >> 4) & 0x0FFF;
+        crc = crc ^ tmp ^ CRC_TABLE[((byte >> 4) & 0xF) as usize];
+    }
+    crc
+}
+
+// 度をセミサークル (2^31 / 180 度) に変換する
+fn semicircles(degrees: f64) -> i32 {
+    (degrees * (2f64.powi(31) / 180.0))
+        .round()
+        .clamp(i32::MIN as f64, i32::MAX as f64) as i32
+}
+
+fn fit_timestamp(point: &GPXTrackPoint) -> Option<u32> {
+    let timestamp = point.timestamp().ok()?.timestamp() - FIT_EPOCH_OFFSET;
+    u32::try_from(timestamp).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FITValue {
+    Enum(u8),
+    UInt16(u16),
+    SInt32(i32),
+    UInt32(u32),
+}
+
+impl FITValue {
+    fn base_type(&self) -> u8 {
+        match self {
+            FITValue::Enum(_) => 0x00,
+            FITValue::UInt16(_) => 0x84,
+            FITValue::SInt32(_) => 0x85,
+            FITValue::UInt32(_) => 0x86,
+        }
+    }
+
+    fn size(&self) -> u8 {
+        match self {
+            FITValue::Enum(_) => 1,
+            FITValue::UInt16(_) => 2,
+            FITValue::SInt32(_) | FITValue::UInt32(_) => 4,
+        }
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        match self {
+            FITValue::Enum(v) => buffer.push(*v),
+            FITValue::UInt16(v) => buffer.write_u16::<LittleEndian>(*v).unwrap(),
+            FITValue::SInt32(v) => buffer.write_i32::<LittleEndian>(*v).unwrap(),
+            FITValue::UInt32(v) => buffer.write_u32::<LittleEndian>(*v).unwrap(),
+        }
+    }
+}
+
+// グローバルメッセージ番号と (フィールド番号, 基本型) の並び
+type FITDefinition = (u16, Vec<(u8, u8)>);
+
+// 定義メッセージとデータメッセージを書き出す。定義が変わった時だけ定義メッセージを出す
+struct FITWriter {
+    data: Vec<u8>,
+    definitions: Vec<Option<FITDefinition>>,
+}
+
+impl FITWriter {
+    fn new() -> Self {
+        FITWriter {
+            data: Vec::new(),
+            definitions: vec![None; 16],
+        }
+    }
+
+    fn write_message(&mut self, local: u8, global: u16, fields: &[(u8, FITValue)]) {
+        let signature = fields
+            .iter()
+            .map(|(number, value)| (*number, value.base_type()))
+            .collect::<Vec<(u8, u8)>>();
+        let definition = Some((global, signature));
+        if self.definitions[local as usize] != definition {
+            self.data.push(0x40 | local);
+            self.data.push(0); // reserved
+            self.data.push(0); // little endian
+            self.data.write_u16::<LittleEndian>(global).unwrap();
+            self.data.push(fields.len() as u8);
+            for (number, value) in fields.iter() {
+                self.data.push(*number);
+                self.data.push(value.size());
+                self.data.push(value.base_type());
+            }
+            self.definitions[local as usize] = definition;
+        }
+
+        self.data.push(local);
+        for (_, value) in fields.iter() {
+            value.write(&mut self.data);
+        }
+    }
+}
+
+// ラップ・セッションの集計値
+struct FITSummary {
+    start_time: u32,
+    end_time: u32,
+    start_position: (i32, i32),
+    end_position: (i32, i32),
+    distance: f64,
+    max_speed: f64,
+}
+
+impl FITSummary {
+    fn from_records(records: &[FITRecord]) -> Option<Self> {
+        let first = records.first()?;
+        let last = records.last()?;
+        Some(FITSummary {
+            start_time: first.timestamp,
+            end_time: last.timestamp,
+            start_position: first.position,
+            end_position: last.position,
+            distance: last.distance - first.distance,
+            max_speed: records.iter().map(|r| r.speed).fold(0.0, f64::max),
+        })
+    }
+
+    fn elapsed_time(&self) -> u32 {
+        self.end_time.saturating_sub(self.start_time)
+    }
+
+    // ミリ秒だと u32 は 49 日ほどで溢れるので上限で止める
+    fn elapsed_time_ms(&self) -> u32 {
+        (self.elapsed_time() as u64 * 1000).min(u32::MAX as u64) as u32
+    }
+
+    fn avg_speed(&self) -> f64 {
+        if self.elapsed_time() == 0 {
+            0.0
+        } else {
+            self.distance / self.elapsed_time() as f64
+        }
+    }
+
+    fn fields(&self) -> Vec<(u8, FITValue)> {
+        vec![
+            (253, FITValue::UInt32(self.end_time)),
+            (2, FITValue::UInt32(self.start_time)),
+            (3, FITValue::SInt32(self.start_position.0)),
+            (4, FITValue::SInt32(self.start_position.1)),
+            (7, FITValue::UInt32(self.elapsed_time_ms())),
+            (8, FITValue::UInt32(self.elapsed_time_ms())),
+            (9, FITValue::UInt32((self.distance * 100.0).round() as u32)),
+        ]
+    }
+}
+
+#[derive(Clone)]
+struct FITRecord {
+    timestamp: u32,
+    position: (i32, i32),
+    altitude: Option<f64>,
+    // m/s
+    speed: f64,
+    // 開始地点からの累積距離 (m)
+    distance: f64,
+}
+
+#[derive(Debug)]
+pub(crate) struct FIT {
+    children: Vec<GPXTrackLog>,
+    segment_gap: GPXSegmentGap,
+    timezone: Tz,
+}
+
+impl FIT {
+    pub(crate) fn new(children: Vec<GPXTrackLog>) -> Self {
+        FIT {
+            children,
+            segment_gap: GPXSegmentGap::default(),
+            timezone: chrono_tz::Asia::Tokyo,
+        }
+    }
+
+    // 区間の切れ目をまたぐ移動は距離に含めない
+    pub(crate) fn set_segment_gap(&mut self, segment_gap: GPXSegmentGap) {
+        self.segment_gap = segment_gap;
+    }
+
+    pub(crate) fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = timezone;
+    }
+
+    // FIT の時刻に timezone の UTC からのずれを足したもの
+    fn local_timestamp(&self, timestamp: u32) -> u32 {
+        let offset = DateTime::from_timestamp(timestamp as i64 + FIT_EPOCH_OFFSET, 0)
+            .map(|time| {
+                time.with_timezone(&self.timezone)
+                    .offset()
+                    .fix()
+                    .local_minus_utc()
+            })
+            .unwrap_or(0);
+        (timestamp as i64 + offset as i64).max(0) as u32
+    }
+
+    // 元ファイルごとにラップとして分けたレコードを返す
+    fn laps(&self) -> Vec<Vec<FITRecord>> {
+        let mut laps = Vec::new();
+        let mut distance = 0.0;
+        let mut last_point: Option<&GPXTrackPoint> = None;
+        for child in self.children.iter() {
+            for range in child.clip_ranges() {
+                let mut records = Vec::new();
+                for point in child.points()[range].iter() {
+                    let timestamp = match fit_timestamp(point) {
+                        Some(timestamp) => timestamp,
+                        None => continue,
+                    };
+                    if let Some(last_point) = last_point {
+                        if !self.segment_gap.is_gap(last_point, point) {
+                            distance += last_point.distance_to(point);
+                        }
+                    }
+                    last_point = Some(point);
+                    records.push(FITRecord {
+                        timestamp,
+                        position: (semicircles(point.lat()), semicircles(point.lon())),
+                        altitude: point.ele(),
+                        speed: point.speed() / 3.6,
+                        distance,
+                    });
+                }
+                if !records.is_empty() {
+                    laps.push(records);
+                }
+            }
+        }
+        laps
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let laps = self.laps();
+        let all_records = laps.iter().flatten().cloned().collect::<Vec<FITRecord>>();
+        let mut writer = FITWriter::new();
+
+        let time_created = all_records.first().map(|r| r.timestamp).unwrap_or(0);
+        writer.write_message(
+            0,
+            MESG_FILE_ID,
+            &[
+                (0, FITValue::Enum(FILE_ACTIVITY)),
+                (1, FITValue::UInt16(MANUFACTURER_DEVELOPMENT)),
+                (2, FITValue::UInt16(0)),
+                (4, FITValue::UInt32(time_created)),
+            ],
+        );
+
+        if let (Some(first), Some(last)) = (all_records.first(), all_records.last()) {
+            writer.write_message(
+                1,
+                MESG_EVENT,
+                &[
+                    (253, FITValue::UInt32(first.timestamp)),
+                    (0, FITValue::Enum(EVENT_TIMER)),
+                    (1, FITValue::Enum(EVENT_TYPE_START)),
+                ],
+            );
+
+            for record in all_records.iter() {
+                let mut fields = vec![
+                    (253, FITValue::UInt32(record.timestamp)),
+                    (0, FITValue::SInt32(record.position.0)),
+                    (1, FITValue::SInt32(record.position.1)),
+                    (
+                        5,
+                        FITValue::UInt32((record.distance * 100.0).round() as u32),
+                    ),
+                    (6, FITValue::UInt16((record.speed * 1000.0).round() as u16)),
+                ];
+                if let Some(altitude) = record.altitude {
+                    fields.push((
+                        2,
+                        FITValue::UInt16(((altitude + 500.0) * 5.0).round() as u16),
+                    ));
+                }
+                writer.write_message(2, MESG_RECORD, &fields);
+            }
+
+            writer.write_message(
+                1,
+                MESG_EVENT,
+                &[
+                    (253, FITValue::UInt32(last.timestamp)),
+                    (0, FITValue::Enum(EVENT_TIMER)),
+                    (1, FITValue::Enum(EVENT_TYPE_STOP_ALL)),
+                ],
+            );
+        }
+
+        for (i, lap) in laps.iter().enumerate() {
+            let summary = match FITSummary::from_records(lap) {
+                Some(summary) => summary,
+                None => continue,
+            };
+            let mut fields = summary.fields();
+            fields.extend([
+                (254, FITValue::UInt16(i as u16)),
+                (0, FITValue::Enum(EVENT_LAP)),
+                (1, FITValue::Enum(EVENT_TYPE_STOP)),
+                (5, FITValue::SInt32(summary.end_position.0)),
+                (6, FITValue::SInt32(summary.end_position.1)),
+                (
+                    13,
+                    FITValue::UInt16((summary.avg_speed() * 1000.0).round() as u16),
+                ),
+                (
+                    14,
+                    FITValue::UInt16((summary.max_speed * 1000.0).round() as u16),
+                ),
+                (25, FITValue::Enum(SPORT_DRIVING)),
+            ]);
+            writer.write_message(3, MESG_LAP, &fields);
+        }
+
+        if let Some(summary) = FITSummary::from_records(&all_records) {
+            let mut fields = summary.fields();
+            fields.extend([
+                (254, FITValue::UInt16(0)),
+                (0, FITValue::Enum(EVENT_SESSION)),
+                (1, FITValue::Enum(EVENT_TYPE_STOP)),
+                (5, FITValue::Enum(SPORT_DRIVING)),
+                (
+                    14,
+                    FITValue::UInt16((summary.avg_speed() * 1000.0).round() as u16),
+                ),
+                (
+                    15,
+                    FITValue::UInt16((summary.max_speed * 1000.0).round() as u16),
+                ),
+                (25, FITValue::UInt16(0)),
+                (26, FITValue::UInt16(laps.len() as u16)),
+            ]);
+            writer.write_message(4, MESG_SESSION, &fields);
+
+            writer.write_message(
+                5,
+                MESG_ACTIVITY,
+                &[
+                    (253, FITValue::UInt32(summary.end_time)),
+                    (0, FITValue::UInt32(summary.elapsed_time_ms())),
+                    (1, FITValue::UInt16(1)),
+                    (2, FITValue::Enum(0)),
+                    (3, FITValue::Enum(EVENT_ACTIVITY)),
+                    (4, FITValue::Enum(EVENT_TYPE_STOP)),
+                    (5, FITValue::UInt32(self.local_timestamp(summary.end_time))),
+                ],
+            );
+        }
+
+        let mut buffer = Vec::with_capacity(HEADER_SIZE as usize + writer.data.len() + 2);
+        buffer.push(HEADER_SIZE);
+        buffer.push(PROTOCOL_VERSION);
+        buffer.write_u16::<LittleEndian>(PROFILE_VERSION).unwrap();
+        buffer
+            .write_u32::<LittleEndian>(writer.data.len() as u32)
+            .unwrap();
+        buffer.extend_from_slice(b".FIT");
+        let header_crc = crc16(0, &buffer);
+        buffer.write_u16::<LittleEndian>(header_crc).unwrap();
+        buffer.extend_from_slice(&writer.data);
+        let file_crc = crc16(0, &buffer);
+        buffer.write_u16::<LittleEndian>(file_crc).unwrap();
+        buffer
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|e| format!("Failed to write FIT file: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log() -> GPXTrackLog {
+        let mut log = GPXTrackLog::new();
+        log.add_source("a.AVI");
+        for i in 0..3 {
+            log.push(GPXTrackPoint::new(
+                35.0 + i as f64 * 0.001,
+                139.0,
+                None,
+                36.0,
+                format!("2025-07-20 12:00:0{}", i),
+            ));
+        }
+        log.add_source("b.AVI");
+        for i in 3..6 {
+            log.push(GPXTrackPoint::new(
+                35.0 + i as f64 * 0.001,
+                139.0,
+                Some(10.0),
+                72.0,
+                format!("2025-07-20 12:00:0{}", i),
+            ));
+        }
+        log
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        // CRC-16/ARC のチェック値
+        assert_eq!(crc16(0, b"123456789"), 0xBB3D);
+        assert_eq!(crc16(0, b""), 0);
+    }
+
+    #[test]
+    fn file_crc_is_valid() {
+        let bytes = FIT::new(vec![sample_log()]).to_bytes();
+
+        assert_eq!(bytes[0], HEADER_SIZE);
+        assert_eq!(&bytes[8..12], b".FIT");
+        let header_crc = u16::from_le_bytes([bytes[12], bytes[13]]);
+        assert_eq!(header_crc, crc16(0, &bytes[..12]));
+
+        let data_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        assert_eq!(bytes.len(), HEADER_SIZE as usize + data_size + 2);
+
+        // CRC まで含めて計算すると 0 になる
+        assert_eq!(crc16(0, &bytes), 0);
+    }
+
+    #[test]
+    fn laps_follow_source_files() {
+        let laps = FIT::new(vec![sample_log()]).laps();
+        assert_eq!(laps.len(), 2);
+        assert_eq!(laps[0].len(), 3);
+        assert_eq!(laps[1][0].timestamp - laps[0][0].timestamp, 3);
+        assert!(laps[1][2].distance > laps[0][2].distance);
+        assert!((laps[1][0].speed - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn local_timestamp_follows_timezone() {
+        let mut fit = FIT::new(Vec::new());
+        // 2025-07-20T03:00:00Z
+        let timestamp = (1752980400 - FIT_EPOCH_OFFSET) as u32;
+        assert_eq!(fit.local_timestamp(timestamp), timestamp + 9 * 3600);
+        fit.set_timezone(chrono_tz::UTC);
+        assert_eq!(fit.local_timestamp(timestamp), timestamp);
+        // 夏時間中のニューヨークは UTC-4
+        fit.set_timezone(chrono_tz::America::New_York);
+        assert_eq!(fit.local_timestamp(timestamp), timestamp - 4 * 3600);
+    }
+
+    #[test]
+    fn skips_distance_across_segment_gaps() {
+        let mut log = sample_log();
+        // 10 分後に離れた場所で記録を再開した
+        log.push(GPXTrackPoint::new(
+            36.0,
+            139.0,
+            None,
+            0.0,
+            "2025-07-20 12:10:00".to_string(),
+        ));
+        let mut fit = FIT::new(vec![log]);
+        let before = fit.laps()[1].last().unwrap().distance;
+        fit.set_segment_gap(GPXSegmentGap::new(
+            Some(std::time::Duration::from_secs(120)),
+            None,
+        ));
+        let laps = fit.laps();
+        let records = &laps[1];
+        let n = records.len();
+        assert!(before > 100_000.0);
+        assert_eq!(records[n - 1].distance, records[n - 2].distance);
+    }
+
+    #[test]
+    fn clamps_long_elapsed_time() {
+        let summary = FITSummary {
+            start_time: 0,
+            end_time: 60 * 24 * 3600,
+            start_position: (0, 0),
+            end_position: (0, 0),
+            distance: 0.0,
+            max_speed: 0.0,
+        };
+        assert_eq!(summary.elapsed_time_ms(), u32::MAX);
+        let summary = FITSummary {
+            end_time: 3600,
+            ..summary
+        };
+        assert_eq!(summary.elapsed_time_ms(), 3_600_000);
+    }
+
+    #[test]
+    fn semicircle_conversion() {
+        assert_eq!(semicircles(90.0), 1 << 30);
+        assert_eq!(semicircles(-90.0), -(1 << 30));
+        assert_eq!(semicircles(180.0), i32::MAX);
+    }
+
+    #[test]
+    fn timestamp_uses_fit_epoch() {
+        let point = GPXTrackPoint::new(0.0, 0.0, None, 0.0, "1989-12-31 09:00:00".to_string());
+        assert_eq!(fit_timestamp(&point), Some(0));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::gpx::track_log::{path_distance, GPXSegmentGap, GPXTrackLog};
+use crate::gpx::track_point::GPXTrackPoint;
+use crate::json::{escape_json, json_number, json_time};
+
+fn position(point: &GPXTrackPoint) -> String {
+    match point.ele() {
+        Some(ele) => format!("[{:.7},{:.7},{:.2}]", point.lon(), point.lat(), ele),
+        None => format!("[{:.7},{:.7}]", point.lon(), point.lat()),
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct GeoJSON {
+    children: Vec<GPXTrackLog>,
+    segment_gap: GPXSegmentGap,
+    with_points: bool,
+}
+
+impl GeoJSON {
+    pub(crate) fn new(children: Vec<GPXTrackLog>) -> Self {
+        GeoJSON {
+            children,
+            segment_gap: GPXSegmentGap::default(),
+            with_points: false,
+        }
+    }
+
+    pub(crate) fn set_segment_gap(&mut self, segment_gap: GPXSegmentGap) {
+        self.segment_gap = segment_gap;
+    }
+
+    // 各サンプルを Point Feature としても出力する
+    pub(crate) fn set_with_points(&mut self, with_points: bool) {
+        self.with_points = with_points;
+    }
+
+    pub(crate) fn to_str(&self) -> String {
+        let mut features = Vec::new();
+        for child in self.children.iter() {
+            let name = child.name().map(escape_json).unwrap_or("null".to_string());
+            for range in child.segment_ranges(&self.segment_gap) {
+                let segment = &child.points()[range.clone()];
+                let sources = child
+                    .sources_in(&range)
+                    .iter()
+                    .map(|source| escape_json(source))
+                    .collect::<Vec<String>>();
+                // LineString は2点以上必要なので、1点だけの区間は Point にする
+                let geometry = match segment {
+                    [point] => {
+                        format!("{{\"type\":\"Point\",\"coordinates\":{}}}", position(point))
+                    }
+                    _ => format!(
+                        "{{\"type\":\"LineString\",\"coordinates\":[{}]}}",
+                        segment
+                            .iter()
+                            .map(position)
+                            .collect::<Vec<String>>()
+                            .join(",")
+                    ),
+                };
+                features.push(format!(
+                    "{{\"type\":\"Feature\",\"geometry\":{},\"properties\":{{\"name\":{},\"start_time\":{},\"end_time\":{},\"distance\":{:.1},\"sources\":[{}]}}}}",
+                    geometry,
+                    name,
+                    json_time(segment.first()),
+                    json_time(segment.last()),
+                    path_distance(segment),
+                    sources.join(",")
+                ));
+            }
+
+            if self.with_points {
+                features.extend(child.points().iter().map(point_feature));
+            }
+        }
+
+        format!(
+            "{{\"type\":\"FeatureCollection\",\"features\":[\n{}\n]}}\n",
+            features.join(",\n")
+        )
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let geojson_string = self.to_str();
+        std::fs::write(path, geojson_string)
+            .map_err(|e| format!("Failed to write GeoJSON file: {}", e))
+    }
+}
+
+fn point_feature(point: &GPXTrackPoint) -> String {
+    let acceleration = match point.acceleration() {
+        Some([x, y, z]) => format!(
+            "{{\"x\":{},\"y\":{},\"z\":{}}}",
+            json_number(Some(x)),
+            json_number(Some(y)),
+            json_number(Some(z))
+        ),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"Point\",\"coordinates\":{}}},\"properties\":{{\"time\":{},\"speed\":{},\"acceleration\":{},\"voltage\":{},\"event\":{},\"synthesized\":{}}}}}",
+        position(point),
+        json_time(Some(point)),
+        json_number(Some(point.speed())),
+        acceleration,
+        json_number(point.voltage()),
+        point.event().map(escape_json).unwrap_or("null".to_string()),
+        point.is_synthesized()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, time: &str) -> GPXTrackPoint {
+        GPXTrackPoint::new(lat, 139.0, None, 36.0, format!("2025-07-20 {}", time))
+    }
+
+    #[test]
+    fn writes_segments_as_features() {
+        let mut log = GPXTrackLog::new();
+        log.set_name("trip \"A\"");
+        log.add_source("a.AVI");
+        log.push(point(35.0, "12:00:00"));
+        log.push(point(35.0001, "12:00:01"));
+        log.push(point(35.0002, "12:00:02"));
+        // 区間の間隔を超えて1点だけ離れた区間
+        log.add_source("b.AVI");
+        log.push(point(35.01, "12:10:00"));
+        let mut geojson = GeoJSON::new(vec![log]);
+        geojson.set_segment_gap(GPXSegmentGap::new(
+            Some(std::time::Duration::from_secs(120)),
+            None,
+        ));
+
+        let s = geojson.to_str();
+        let lines = s.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines.first(),
+            Some(&"{\"type\":\"FeatureCollection\",\"features\":[")
+        );
+        assert_eq!(lines.last(), Some(&"]}"));
+        let features = &lines[1..lines.len() - 1];
+        assert_eq!(features.len(), 2);
+
+        assert!(features[0].starts_with(
+            "{\"type\":\"Feature\",\"geometry\":{\"type\":\"LineString\",\"coordinates\":[[139.0000000,35.0000000],[139.0000000,35.0001000],[139.0000000,35.0002000]]},"
+        ));
+        assert!(features[0].contains("\"name\":\"trip \\\"A\\\"\""));
+        assert!(features[0].contains(
+            "\"start_time\":\"2025-07-20T12:00:00+09:00\",\"end_time\":\"2025-07-20T12:00:02+09:00\""
+        ));
+        assert!(features[0].ends_with("\"sources\":[\"a.AVI\"]}},"));
+
+        assert_eq!(
+            features[1],
+            "{\"type\":\"Feature\",\"geometry\":{\"type\":\"Point\",\"coordinates\":[139.0000000,35.0100000]},\"properties\":{\"name\":\"trip \\\"A\\\"\",\"start_time\":\"2025-07-20T12:10:00+09:00\",\"end_time\":\"2025-07-20T12:10:00+09:00\",\"distance\":0.0,\"sources\":[\"b.AVI\"]}}"
+        );
+    }
+}
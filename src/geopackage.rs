@@ -0,0 +1,645 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use rusqlite::{params, Connection};
+
+use crate::gpx::track_log::{path_distance, GPXSegmentGap, GPXTrackLog};
+use crate::gpx::track_point::GPXTrackPoint;
+use crate::zdr055::ZDR055PositionData;
+
+const APPLICATION_ID: i32 = 0x4750_4B47; // "GPKG"
+const USER_VERSION: i32 = 10300; // GeoPackage 1.3
+const SRS_ID: i32 = 4326;
+
+const WKB_POINT: u32 = 1;
+const WKB_LINESTRING: u32 = 2;
+const WKB_MULTILINESTRING: u32 = 5;
+
+// (テーブル名, ジオメトリ型, 説明)
+const FEATURE_TABLES: [(&str, &str, &str); 4] = [
+    (
+        "trips",
+        "MULTILINESTRING",
+        "Continuous drives (merged clips)",
+    ),
+    ("clips", "LINESTRING", "Recorded video clips"),
+    ("samples", "POINT", "Raw telemetry samples"),
+    ("events", "POINT", "Points where the event type changes"),
+];
+
+const SCHEMA: &str = "
+CREATE TABLE gpkg_spatial_ref_sys (
+    srs_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL PRIMARY KEY,
+    organization TEXT NOT NULL,
+    organization_coordsys_id INTEGER NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT
+);
+CREATE TABLE gpkg_contents (
+    table_name TEXT NOT NULL PRIMARY KEY,
+    data_type TEXT NOT NULL,
+    identifier TEXT UNIQUE,
+    description TEXT DEFAULT '',
+    last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+    min_x DOUBLE,
+    min_y DOUBLE,
+    max_x DOUBLE,
+    max_y DOUBLE,
+    srs_id INTEGER,
+    CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+);
+CREATE TABLE gpkg_geometry_columns (
+    table_name TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    geometry_type_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL,
+    z TINYINT NOT NULL,
+    m TINYINT NOT NULL,
+    CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+    CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+    CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+);
+CREATE TABLE gpkg_extensions (
+    table_name TEXT,
+    column_name TEXT,
+    extension_name TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    CONSTRAINT ge_tce UNIQUE (table_name, column_name, extension_name)
+);
+INSERT INTO gpkg_spatial_ref_sys VALUES
+    ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', 'undefined cartesian coordinate reference system'),
+    ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', 'undefined geographic coordinate reference system'),
+    ('WGS 84 geodetic', 4326, 'EPSG', 4326, 'GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563,AUTHORITY[\"EPSG\",\"7030\"]],AUTHORITY[\"EPSG\",\"6326\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],AUTHORITY[\"EPSG\",\"4326\"]]', 'longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid');
+CREATE TABLE trips (
+    fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    geom MULTILINESTRING,
+    name TEXT,
+    device TEXT,
+    start_time DATETIME,
+    end_time DATETIME,
+    clip_count INTEGER,
+    point_count INTEGER,
+    distance_m REAL
+);
+CREATE TABLE clips (
+    fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    geom LINESTRING,
+    trip_id INTEGER REFERENCES trips(fid),
+    source TEXT,
+    start_time DATETIME,
+    end_time DATETIME,
+    point_count INTEGER,
+    distance_m REAL,
+    max_speed_kmh REAL
+);
+CREATE TABLE events (
+    fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    geom POINT,
+    trip_id INTEGER REFERENCES trips(fid),
+    clip_id INTEGER REFERENCES clips(fid),
+    time DATETIME,
+    event_type TEXT,
+    previous_event_type TEXT,
+    speed_kmh REAL
+);
+";
+
+// ZDR055PositionData のフィールドに対応する列の型
+fn column_type(field: &str) -> &'static str {
+    match field {
+        "x_accel" | "y_accel" | "z_accel" | "supply_voltage" | "latitude" | "longitude"
+        | "speed" => "REAL",
+        "e_value" | "m_value" | "em_value" | "sa_value" | "firmware_version" | "s_value" => {
+            "INTEGER"
+        }
+        "is_valid" => "BOOLEAN",
+        _ => "TEXT",
+    }
+}
+
+// GeoPackage の DATETIME は UTC の ISO 8601
+fn gpkg_time(point: &GPXTrackPoint) -> Option<String> {
+    let timestamp = point.timestamp().ok()?;
+    Some(
+        timestamp
+            .with_timezone(&chrono::Utc)
+            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            .to_string(),
+    )
+}
+
+fn write_coordinates(buffer: &mut Vec<u8>, points: &[GPXTrackPoint]) {
+    buffer
+        .write_u32::<LittleEndian>(points.len() as u32)
+        .unwrap();
+    for point in points {
+        buffer.write_f64::<LittleEndian>(point.lon()).unwrap();
+        buffer.write_f64::<LittleEndian>(point.lat()).unwrap();
+    }
+}
+
+// GeoPackage バイナリ (ヘッダー + リトルエンディアンの WKB)
+fn geometry(wkb_type: u32, parts: &[&[GPXTrackPoint]]) -> Vec<u8> {
+    let mut buffer = b"GP".to_vec();
+    buffer.push(0); // バージョン
+    let points = parts.iter().flat_map(|part| part.iter());
+    let envelope = points.fold(None, |acc: Option<[f64; 4]>, point| {
+        let (x, y) = (point.lon(), point.lat());
+        Some(match acc {
+            Some([min_x, max_x, min_y, max_y]) => {
+                [min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y)]
+            }
+            None => [x, x, y, y],
+        })
+    });
+    match (wkb_type, envelope) {
+        // 点は包囲矩形を省略する
+        (WKB_POINT, _) | (_, None) => {
+            buffer.push(0x01);
+            buffer.write_i32::<LittleEndian>(SRS_ID).unwrap();
+        }
+        (_, Some(envelope)) => {
+            buffer.push(0x03); // リトルエンディアン、 [minx, maxx, miny, maxy] の包囲矩形
+            buffer.write_i32::<LittleEndian>(SRS_ID).unwrap();
+            for value in envelope {
+                buffer.write_f64::<LittleEndian>(value).unwrap();
+            }
+        }
+    }
+
+    buffer.push(1);
+    buffer.write_u32::<LittleEndian>(wkb_type).unwrap();
+    match wkb_type {
+        WKB_POINT => {
+            let point = &parts[0][0];
+            buffer.write_f64::<LittleEndian>(point.lon()).unwrap();
+            buffer.write_f64::<LittleEndian>(point.lat()).unwrap();
+        }
+        WKB_LINESTRING => write_coordinates(&mut buffer, parts[0]),
+        _ => {
+            buffer
+                .write_u32::<LittleEndian>(parts.len() as u32)
+                .unwrap();
+            for part in parts {
+                buffer.push(1);
+                buffer.write_u32::<LittleEndian>(WKB_LINESTRING).unwrap();
+                write_coordinates(&mut buffer, part);
+            }
+        }
+    }
+    buffer
+}
+
+fn sql_error(e: rusqlite::Error) -> String {
+    format!("Failed to write GeoPackage: {}", e)
+}
+
+// 1回の実行で処理したトリップをすべて1つのファイルに書き込む
+pub(crate) struct GeoPackage {
+    connection: Connection,
+    segment_gap: GPXSegmentGap,
+}
+
+impl GeoPackage {
+    // 既存のファイルは上書きする
+    pub(crate) fn create(path: &std::path::Path) -> Result<Self, String> {
+        if path.exists() {
+            std::fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove old GeoPackage: {}", e))?;
+        }
+        let connection = Connection::open(path).map_err(sql_error)?;
+        connection
+            .pragma_update(None, "application_id", APPLICATION_ID)
+            .map_err(sql_error)?;
+        connection
+            .pragma_update(None, "user_version", USER_VERSION)
+            .map_err(sql_error)?;
+        connection.execute_batch(SCHEMA).map_err(sql_error)?;
+
+        let sample_columns = ZDR055PositionData::default()
+            .fields()
+            .iter()
+            .map(|(field, _)| format!("    {} {}", field, column_type(field)))
+            .collect::<Vec<String>>();
+        connection
+            .execute_batch(&format!(
+                "CREATE TABLE samples (
+    fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    geom POINT,
+    trip_id INTEGER REFERENCES trips(fid),
+    clip_id INTEGER REFERENCES clips(fid),
+    source TEXT,
+    stream_index INTEGER,
+    time DATETIME,
+{}
+);",
+                sample_columns.join(",\n")
+            ))
+            .map_err(sql_error)?;
+
+        for (table, geometry_type, description) in FEATURE_TABLES {
+            connection
+                .execute(
+                    "INSERT INTO gpkg_contents (table_name, data_type, identifier, description, srs_id) VALUES (?1, 'features', ?1, ?2, ?3)",
+                    params![table, description, SRS_ID],
+                )
+                .map_err(sql_error)?;
+            connection
+                .execute(
+                    "INSERT INTO gpkg_geometry_columns VALUES (?1, 'geom', ?2, ?3, 0, 0)",
+                    params![table, geometry_type, SRS_ID],
+                )
+                .map_err(sql_error)?;
+        }
+
+        Ok(GeoPackage {
+            connection,
+            segment_gap: GPXSegmentGap::default(),
+        })
+    }
+
+    pub(crate) fn set_segment_gap(&mut self, segment_gap: GPXSegmentGap) {
+        self.segment_gap = segment_gap;
+    }
+
+    // clips はクリップごとのログと、そのクリップの生のログデータ
+    pub(crate) fn add_trip(
+        &mut self,
+        name: &str,
+        clips: &[(&GPXTrackLog, &[(usize, ZDR055PositionData)])],
+    ) -> Result<(), String> {
+        let mut trip = GPXTrackLog::new();
+        for (log, _) in clips {
+            trip.extend((*log).clone());
+        }
+        if trip.is_empty() && clips.iter().all(|(_, samples)| samples.is_empty()) {
+            return Ok(());
+        }
+
+        let transaction = self.connection.transaction().map_err(sql_error)?;
+        let segments = trip.segments(&self.segment_gap);
+        let trip_geometry = if trip.is_empty() {
+            None
+        } else {
+            Some(geometry(WKB_MULTILINESTRING, &segments))
+        };
+        transaction
+            .execute(
+                "INSERT INTO trips (geom, name, device, start_time, end_time, clip_count, point_count, distance_m) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    trip_geometry,
+                    name,
+                    trip.device().map(|device| device.name()),
+                    trip.first().and_then(gpkg_time),
+                    trip.last().and_then(gpkg_time),
+                    clips.len(),
+                    trip.points().len(),
+                    segments.iter().map(|segment| path_distance(segment)).sum::<f64>(),
+                ],
+            )
+            .map_err(sql_error)?;
+        let trip_id = transaction.last_insert_rowid();
+
+        for (log, samples) in clips {
+            let points = log.points();
+            let clip_geometry = if points.is_empty() {
+                None
+            } else {
+                Some(geometry(WKB_LINESTRING, &[points]))
+            };
+            let source = log.sources().join(", ");
+            transaction
+                .execute(
+                    "INSERT INTO clips (geom, trip_id, source, start_time, end_time, point_count, distance_m, max_speed_kmh) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        clip_geometry,
+                        trip_id,
+                        source,
+                        log.first().and_then(gpkg_time),
+                        log.last().and_then(gpkg_time),
+                        points.len(),
+                        path_distance(points),
+                        points.iter().map(|point| point.speed()).reduce(f64::max),
+                    ],
+                )
+                .map_err(sql_error)?;
+            let clip_id = transaction.last_insert_rowid();
+
+            // 種別が変わった地点をイベントとする
+            let mut last_event: Option<&str> = None;
+            for (i, point) in points.iter().enumerate() {
+                let event = point.event();
+                if i > 0 && event.is_some() && event != last_event {
+                    transaction
+                        .execute(
+                            "INSERT INTO events (geom, trip_id, clip_id, time, event_type, previous_event_type, speed_kmh) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                            params![
+                                geometry(WKB_POINT, &[std::slice::from_ref(point)]),
+                                trip_id,
+                                clip_id,
+                                gpkg_time(point),
+                                event,
+                                last_event,
+                                point.speed(),
+                            ],
+                        )
+                        .map_err(sql_error)?;
+                }
+                last_event = event;
+            }
+
+            for (index, sample) in samples.iter() {
+                let point = sample.to_gpx_point();
+                let fields = sample.fields();
+                let columns = fields
+                    .iter()
+                    .map(|(field, _)| *field)
+                    .collect::<Vec<&str>>();
+                let mut values: Vec<Box<dyn rusqlite::ToSql>> = vec![
+                    Box::new(geometry(WKB_POINT, &[std::slice::from_ref(&point)])),
+                    Box::new(trip_id),
+                    Box::new(clip_id),
+                    Box::new(source.clone()),
+                    Box::new(*index as i64),
+                    Box::new(gpkg_time(&point)),
+                ];
+                // 数値の列は SQLite の型アフィニティで数値として格納される
+                for (field, value) in fields {
+                    if field == "is_valid" {
+                        values.push(Box::new(value == "true"));
+                    } else {
+                        values.push(Box::new(value));
+                    }
+                }
+                let placeholders = (1..=values.len())
+                    .map(|i| format!("?{}", i))
+                    .collect::<Vec<String>>();
+                let mut statement = transaction
+                    .prepare_cached(&format!(
+                        "INSERT INTO samples (geom, trip_id, clip_id, source, stream_index, time, {}) VALUES ({})",
+                        columns.join(", "),
+                        placeholders.join(", ")
+                    ))
+                    .map_err(sql_error)?;
+                statement
+                    .execute(rusqlite::params_from_iter(values.iter()))
+                    .map_err(sql_error)?;
+            }
+        }
+        transaction.commit().map_err(sql_error)
+    }
+
+    // 範囲を更新し、空間インデックスを作る。
+    // 仕様のトリガーは SQLite 単体には無い ST_* 関数を使うので、データを入れ終えてから作成する
+    pub(crate) fn finish(self) -> Result<(), String> {
+        for (table, _, _) in FEATURE_TABLES {
+            let rtree = format!("rtree_{}_geom", table);
+            let mut statement = self
+                .connection
+                .prepare(&format!(
+                    "SELECT fid, geom FROM {} WHERE geom IS NOT NULL",
+                    table
+                ))
+                .map_err(sql_error)?;
+            let rows = statement
+                .query_map([], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+                })
+                .map_err(sql_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(sql_error)?;
+
+            self.connection
+                .execute_batch(&format!(
+                    "CREATE VIRTUAL TABLE {rtree} USING rtree(id, minx, maxx, miny, maxy);"
+                ))
+                .map_err(sql_error)?;
+            let mut extent: Option<[f64; 4]> = None;
+            for (fid, blob) in rows {
+                let [min_x, max_x, min_y, max_y] = match envelope(&blob) {
+                    Some(envelope) => envelope,
+                    None => continue,
+                };
+                self.connection
+                    .execute(
+                        &format!("INSERT INTO {} VALUES (?1, ?2, ?3, ?4, ?5)", rtree),
+                        params![fid, min_x, max_x, min_y, max_y],
+                    )
+                    .map_err(sql_error)?;
+                extent = Some(match extent {
+                    Some([x0, x1, y0, y1]) => {
+                        [x0.min(min_x), x1.max(max_x), y0.min(min_y), y1.max(max_y)]
+                    }
+                    None => [min_x, max_x, min_y, max_y],
+                });
+            }
+            if let Some([min_x, max_x, min_y, max_y]) = extent {
+                self.connection
+                    .execute(
+                        "UPDATE gpkg_contents SET min_x = ?1, min_y = ?2, max_x = ?3, max_y = ?4 WHERE table_name = ?5",
+                        params![min_x, min_y, max_x, max_y, table],
+                    )
+                    .map_err(sql_error)?;
+            }
+
+            self.connection
+                .execute(
+                    "INSERT INTO gpkg_extensions VALUES (?1, 'geom', 'gpkg_rtree_index', 'http://www.geopackage.org/spec120/#extension_rtree', 'write-only')",
+                    params![table],
+                )
+                .map_err(sql_error)?;
+            self.connection
+                .execute_batch(&rtree_triggers(table, &rtree))
+                .map_err(sql_error)?;
+        }
+        Ok(())
+    }
+}
+
+// geometry() で書いた GeoPackage バイナリから [minx, maxx, miny, maxy] を取り出す
+fn envelope(blob: &[u8]) -> Option<[f64; 4]> {
+    let read = |offset: usize| -> Option<f64> {
+        Some(f64::from_le_bytes(
+            blob.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    };
+    match blob.get(3)? & 0x0e {
+        // 包囲矩形を持つ場合はそのまま使う
+        0x02 => Some([read(8)?, read(16)?, read(24)?, read(32)?]),
+        // 点は WKB の座標を使う (ヘッダー 8 バイト + バイト順 1 バイト + 型 4 バイト)
+        0x00 => {
+            let (x, y) = (read(13)?, read(21)?);
+            Some([x, x, y, y])
+        }
+        _ => None,
+    }
+}
+
+fn rtree_triggers(table: &str, rtree: &str) -> String {
+    let values =
+        "NEW.fid, ST_MinX(NEW.geom), ST_MaxX(NEW.geom), ST_MinY(NEW.geom), ST_MaxY(NEW.geom)";
+    format!(
+        "CREATE TRIGGER {rtree}_insert AFTER INSERT ON {table}
+  WHEN (NEW.geom NOT NULL AND NOT ST_IsEmpty(NEW.geom))
+BEGIN
+  INSERT OR REPLACE INTO {rtree} VALUES ({values});
+END;
+CREATE TRIGGER {rtree}_update1 AFTER UPDATE OF geom ON {table}
+  WHEN OLD.fid = NEW.fid AND (NEW.geom NOTNULL AND NOT ST_IsEmpty(NEW.geom))
+BEGIN
+  INSERT OR REPLACE INTO {rtree} VALUES ({values});
+END;
+CREATE TRIGGER {rtree}_update2 AFTER UPDATE OF geom ON {table}
+  WHEN OLD.fid = NEW.fid AND (NEW.geom ISNULL OR ST_IsEmpty(NEW.geom))
+BEGIN
+  DELETE FROM {rtree} WHERE id = OLD.fid;
+END;
+CREATE TRIGGER {rtree}_update3 AFTER UPDATE ON {table}
+  WHEN OLD.fid != NEW.fid AND (NEW.geom NOTNULL AND NOT ST_IsEmpty(NEW.geom))
+BEGIN
+  DELETE FROM {rtree} WHERE id = OLD.fid;
+  INSERT OR REPLACE INTO {rtree} VALUES ({values});
+END;
+CREATE TRIGGER {rtree}_update4 AFTER UPDATE ON {table}
+  WHEN OLD.fid != NEW.fid AND (NEW.geom ISNULL OR ST_IsEmpty(NEW.geom))
+BEGIN
+  DELETE FROM {rtree} WHERE id IN (OLD.fid, NEW.fid);
+END;
+CREATE TRIGGER {rtree}_delete AFTER DELETE ON {table}
+  WHEN OLD.geom NOT NULL
+BEGIN
+  DELETE FROM {rtree} WHERE id = OLD.fid;
+END;"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn sample(time: &str, lat: f64, event: &str) -> (usize, ZDR055PositionData) {
+        let line = format!(
+            "ZDR055:2025-07-20 {} X:0.01 Y:-0.02 Z:1.00 T:0 12.3V {} {:.6} N 139.760000 E 40.0km/h E:0 M:1 EM:0 SA:2 V:13 S:120k 0A",
+            time, event, lat
+        );
+        (0, ZDR055PositionData::from_str(&line).unwrap())
+    }
+
+    fn clip(source: &str, samples: &[(usize, ZDR055PositionData)]) -> GPXTrackLog {
+        let mut log = GPXTrackLog::new();
+        log.add_source(source);
+        for (_, sample) in samples {
+            log.push(sample.to_gpx_point());
+        }
+        log
+    }
+
+    #[test]
+    fn writes_features() {
+        let path = std::env::temp_dir().join(format!("zdr055_gpx_{}.gpkg", std::process::id()));
+        let first = vec![
+            sample("12:48:10", 35.68, "N"),
+            sample("12:48:11", 35.681, "N"),
+        ];
+        let second = vec![
+            sample("12:48:12", 35.682, "N"),
+            sample("12:48:13", 35.683, "E"),
+        ];
+        let (first_log, second_log) = (clip("a.AVI", &first), clip("b.AVI", &second));
+        let mut gpkg = GeoPackage::create(&path).unwrap();
+        gpkg.add_trip(
+            "trip",
+            &[(&first_log, &first[..]), (&second_log, &second[..])],
+        )
+        .unwrap();
+        gpkg.finish().unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let application_id: i32 = connection
+            .pragma_query_value(None, "application_id", |row| row.get(0))
+            .unwrap();
+        assert_eq!(application_id, APPLICATION_ID);
+
+        let contents = connection
+            .prepare("SELECT c.table_name, c.data_type, c.srs_id, g.geometry_type_name, c.min_y, c.max_y FROM gpkg_contents c JOIN gpkg_geometry_columns g USING (table_name) ORDER BY c.table_name")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i32>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<f64>>(4)?,
+                    row.get::<_, Option<f64>>(5)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let tables = contents
+            .iter()
+            .map(|(table, data_type, srs_id, geometry_type, _, _)| {
+                assert_eq!((data_type.as_str(), *srs_id), ("features", SRS_ID));
+                (table.as_str(), geometry_type.as_str())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tables,
+            vec![
+                ("clips", "LINESTRING"),
+                ("events", "POINT"),
+                ("samples", "POINT"),
+                ("trips", "MULTILINESTRING"),
+            ]
+        );
+        // 範囲は全点を包む
+        let (_, _, _, _, min_y, max_y) = &contents[3];
+        assert_eq!((*min_y, *max_y), (Some(35.68), Some(35.683)));
+
+        let count = |table: &str| -> i64 {
+            connection
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+        assert_eq!(count("trips"), 1);
+        assert_eq!(count("clips"), 2);
+        assert_eq!(count("samples"), 4);
+        assert_eq!(count("events"), 1);
+        assert_eq!(count("rtree_samples_geom"), 4);
+
+        let (clip_count, point_count): (i64, i64) = connection
+            .query_row("SELECT clip_count, point_count FROM trips", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((clip_count, point_count), (2, 4));
+        let sources = connection
+            .prepare("SELECT source FROM clips ORDER BY fid")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(sources, vec!["a.AVI", "b.AVI"]);
+
+        // GeoPackage バイナリのヘッダーと WKB の点
+        let geom: Vec<u8> = connection
+            .query_row("SELECT geom FROM samples ORDER BY fid LIMIT 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(&geom[..2], b"GP");
+        assert_eq!(i32::from_le_bytes(geom[4..8].try_into().unwrap()), SRS_ID);
+        assert_eq!(envelope(&geom), Some([139.76, 139.76, 35.68, 35.68]));
+        let geom: Vec<u8> = connection
+            .query_row("SELECT geom FROM trips", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(envelope(&geom), Some([139.76, 139.76, 35.68, 35.683]));
+
+        drop(connection);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,21 +1,75 @@
+pub(crate) mod metadata;
+pub(crate) mod outlier;
+pub(crate) mod reader;
+pub(crate) mod simplify;
+pub(crate) mod smooth;
 pub(crate) mod track_log;
 pub(crate) mod track_point;
 
-use track_log::GPXTrackLog;
+use std::str::FromStr;
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+use metadata::{GPXDevice, GPXMetadata, ZDR_NAMESPACE};
+use reader::{parse_gpx_time, parse_speed_desc, XMLReader, XMLToken};
+use track_log::{GPXSegmentGap, GPXTrackLog};
+use track_point::GPXTrackPoint;
 
 #[derive(Debug)]
 pub(crate) struct GPX {
-    child: GPXTrackLog,
+    metadata: Option<GPXMetadata>,
+    children: Vec<GPXTrackLog>,
+    segment_gap: GPXSegmentGap,
 }
 
 impl GPX {
-    pub(crate) fn new(child: GPXTrackLog) -> Self {
-        GPX { child }
+    pub(crate) fn new(children: Vec<GPXTrackLog>) -> Self {
+        GPX {
+            metadata: None,
+            children,
+            segment_gap: GPXSegmentGap::default(),
+        }
+    }
+
+    pub(crate) fn set_segment_gap(&mut self, segment_gap: GPXSegmentGap) {
+        self.segment_gap = segment_gap;
+    }
+
+    pub(crate) fn set_metadata(&mut self, metadata: GPXMetadata) {
+        self.metadata = Some(metadata);
+    }
+
+    pub(crate) fn metadata(&self) -> Option<&GPXMetadata> {
+        self.metadata.as_ref()
+    }
+
+    pub(crate) fn into_tracks(self) -> Vec<GPXTrackLog> {
+        self.children
     }
 
     pub(crate) fn to_str(&self) -> String {
+        let metadata = match &self.metadata {
+            Some(metadata) => {
+                let bounds = self
+                    .children
+                    .iter()
+                    .filter_map(|child| child.bounds())
+                    .reduce(|mut acc, b| {
+                        acc.extend(&b);
+                        acc
+                    });
+                metadata.to_gpx_string(bounds)
+            }
+            None => String::new(),
+        };
+        let tracks = self
+            .children
+            .iter()
+            .map(|child| child.to_str(&self.segment_gap))
+            .collect::<Vec<String>>();
         format!(
-        "<gpx xmlns=\"http://www.topografix.com/GPX/1/1\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd\" version=\"1.1\" creator=\"zdr055_gpx\">\n{}\n</gpx>", self.child.to_str()
+        "<gpx xmlns=\"http://www.topografix.com/GPX/1/1\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd\" xmlns:zdr=\"{}\" version=\"1.1\" creator=\"zdr055_gpx\">\n{}{}\n</gpx>", ZDR_NAMESPACE, metadata, tracks.join("")
         )
     }
 
@@ -23,4 +77,328 @@ impl GPX {
         let gpx_string = self.to_str();
         std::fs::write(path, gpx_string).map_err(|e| format!("Failed to write GPX file: {}", e))
     }
+
+    pub(crate) fn load(path: &std::path::Path, timezone: Tz) -> Result<Self, String> {
+        let gpx_string =
+            std::fs::read_to_string(path).map_err(|e| format!("Failed to read GPX file: {}", e))?;
+        GPX::parse(&gpx_string, timezone)
+    }
+}
+
+// <trkpt> を読み終えるまでの一時的な値
+struct TrackPointFields {
+    lat: f64,
+    lon: f64,
+    ele: Option<f64>,
+    speed: f64,
+    time: String,
+    event: Option<String>,
+    acceleration: [Option<f64>; 3],
+    voltage: Option<f64>,
+    synthesized: bool,
+}
+
+impl TrackPointFields {
+    fn from_attributes(attributes: &[(String, String)]) -> Result<Self, String> {
+        let get = |key: &str| -> Result<f64, String> {
+            let value = attributes
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v)
+                .ok_or(format!("Missing {} attribute in <trkpt>", key))?;
+            value
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("Failed to parse {}: {}", key, value))
+        };
+        Ok(TrackPointFields {
+            lat: get("lat")?,
+            lon: get("lon")?,
+            ele: None,
+            speed: 0.0,
+            time: String::new(),
+            event: None,
+            acceleration: [None; 3],
+            voltage: None,
+            synthesized: false,
+        })
+    }
+
+    fn into_point(self) -> GPXTrackPoint {
+        let mut point = GPXTrackPoint::new(self.lat, self.lon, self.ele, self.speed, self.time);
+        point.set_event(self.event);
+        if let [Some(x), Some(y), Some(z)] = self.acceleration {
+            point.set_acceleration(Some([x, y, z]));
+        }
+        point.set_voltage(self.voltage);
+        point.set_synthesized(self.synthesized);
+        point
+    }
+}
+
+// GPX 1.0 / 1.1 のどちらも読めるようにしている
+// 時差の書かれていない時刻は timezone の時刻として読む
+impl GPX {
+    pub(crate) fn parse(s: &str, timezone: Tz) -> Result<Self, String> {
+        let mut reader = XMLReader::new(s);
+        let mut path: Vec<String> = Vec::new();
+        let mut found_root = false;
+
+        let mut name = String::new();
+        let mut desc = String::new();
+        let mut time = None;
+        let mut device_name = None;
+        let mut firmware_version = None;
+        let mut sources = Vec::new();
+
+        let mut tracks = Vec::new();
+        let mut track: Option<GPXTrackLog> = None;
+        let mut point: Option<TrackPointFields> = None;
+
+        while let Some(token) = reader.next_token()? {
+            match token {
+                XMLToken::Start {
+                    name: element,
+                    attributes,
+                    self_closing,
+                } => {
+                    match element.as_str() {
+                        "gpx" => found_root = true,
+                        // <trkseg> の区切りは保持せず1つのログにつなげる。
+                        // 区間は出力時に --segment-gap で改めて分ける
+                        "trk" => track = Some(GPXTrackLog::new()),
+                        "trkpt" => point = Some(TrackPointFields::from_attributes(&attributes)?),
+                        _ => {}
+                    }
+                    path.push(element);
+                    if !self_closing {
+                        continue;
+                    }
+                }
+                XMLToken::Text(text) => {
+                    let current = path.last().map(|s| s.as_str()).unwrap_or_default();
+                    let parent = path
+                        .len()
+                        .checked_sub(2)
+                        .map(|i| path[i].as_str())
+                        .unwrap_or_default();
+                    let in_metadata = path.iter().any(|e| e == "metadata");
+
+                    if let Some(point) = point.as_mut() {
+                        match (parent, current) {
+                            ("trkpt", "ele") => {
+                                point.ele = Some(
+                                    text.trim()
+                                        .parse::<f64>()
+                                        .map_err(|_| format!("Failed to parse ele: {}", text))?,
+                                );
+                            }
+                            ("trkpt", "time") => point.time = parse_gpx_time(&text, timezone)?,
+                            ("trkpt", "desc") => {
+                                if let Some(speed) = parse_speed_desc(&text) {
+                                    point.speed = speed;
+                                }
+                            }
+                            // GPX 1.0 の <speed> は m/s
+                            ("trkpt", "speed") => {
+                                if let Ok(speed) = text.trim().parse::<f64>() {
+                                    point.speed = speed * 3.6;
+                                }
+                            }
+                            ("extensions", "event") => point.event = Some(text.trim().to_string()),
+                            ("extensions", "x_accel") => {
+                                point.acceleration[0] = text.trim().parse().ok()
+                            }
+                            ("extensions", "y_accel") => {
+                                point.acceleration[1] = text.trim().parse().ok()
+                            }
+                            ("extensions", "z_accel") => {
+                                point.acceleration[2] = text.trim().parse().ok()
+                            }
+                            ("extensions", "voltage") => point.voltage = text.trim().parse().ok(),
+                            ("extensions", "synthesized") => {
+                                point.synthesized = text.trim() == "true"
+                            }
+                            _ => {}
+                        }
+                    } else if let Some(track) = track.as_mut() {
+                        match (parent, current) {
+                            ("trk", "name") => track.set_name(text.trim()),
+                            (_, "source") => track.add_source(text.trim()),
+                            _ => {}
+                        }
+                    } else if in_metadata || parent == "gpx" {
+                        match current {
+                            "name" if parent == "metadata" || parent == "gpx" => {
+                                name = text.trim().to_string();
+                            }
+                            "desc" if parent == "metadata" || parent == "gpx" => {
+                                desc = text.trim().to_string();
+                            }
+                            "time" if parent == "metadata" || parent == "gpx" => {
+                                time = DateTime::parse_from_rfc3339(text.trim()).ok();
+                            }
+                            "device" => device_name = Some(text.trim().to_string()),
+                            "firmware_version" => {
+                                firmware_version = Some(text.trim().to_string());
+                            }
+                            "source" => sources.push(text.trim().to_string()),
+                            _ => {}
+                        }
+                    }
+                    continue;
+                }
+                XMLToken::End { .. } => {}
+            }
+
+            // 終了タグ (または空要素タグ) の処理
+            let element = path.pop().ok_or("Unexpected end tag")?;
+            match element.as_str() {
+                "trkpt" => {
+                    if let (Some(track), Some(point)) = (track.as_mut(), point.take()) {
+                        track.push(point.into_point());
+                    }
+                }
+                "trk" => {
+                    if let Some(track) = track.take() {
+                        tracks.push(track);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !found_root {
+            return Err("Not a GPX file".to_string());
+        }
+
+        let mut gpx = GPX::new(tracks);
+        let mut metadata = GPXMetadata::new(&name, &desc);
+        if let Some(time) = time {
+            metadata.set_time(time.with_timezone(&chrono_tz::Asia::Tokyo));
+        }
+        if let Some(device_name) = device_name {
+            metadata.set_device(GPXDevice::new(
+                &device_name,
+                &firmware_version.unwrap_or_default(),
+            ));
+        }
+        for source in sources {
+            metadata.add_source(&source);
+        }
+        gpx.set_metadata(metadata);
+        Ok(gpx)
+    }
+}
+
+impl FromStr for GPX {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GPX::parse(s, chrono_tz::Asia::Tokyo)
+    }
+}
+
+pub(crate) fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(time: &str, ele: Option<f64>) -> GPXTrackPoint {
+        GPXTrackPoint::new(35.6812362, 139.7671248, ele, 42.5, time.to_string())
+    }
+
+    #[test]
+    fn round_trips_own_output() {
+        let mut log = GPXTrackLog::new();
+        log.set_name("20250720_124810_F");
+        log.add_source("20250720_124810_F_S_Nor.AVI");
+        let mut first = point("2025-07-20 12:48:10", Some(12.5));
+        first.set_event(Some("Normal".to_string()));
+        first.set_acceleration(Some([0.01, -0.02, 1.0]));
+        first.set_voltage(Some(12.3));
+        log.push(first);
+        log.add_source("20250720_124910_F_S_Nor.AVI");
+        let mut second = point("2025-07-20 12:49:10", None);
+        second.set_synthesized(true);
+        log.push(second);
+
+        let mut metadata = GPXMetadata::new("A & B", "<desc>");
+        metadata.set_time(
+            DateTime::parse_from_rfc3339("2025-07-21T00:00:00+09:00")
+                .unwrap()
+                .with_timezone(&chrono_tz::Asia::Tokyo),
+        );
+        metadata.set_device(GPXDevice::new("ZDR055", "1.0.0"));
+        metadata.add_source("20250720_124810_F_S_Nor.AVI");
+        metadata.add_source("20250720_124910_F_S_Nor.AVI");
+        let mut gpx = GPX::new(vec![log]);
+        gpx.set_metadata(metadata);
+
+        let s = gpx.to_str();
+        let read = GPX::from_str(&s).unwrap();
+        assert_eq!(read.to_str(), s);
+
+        let metadata = read.metadata().unwrap();
+        assert_eq!(metadata.device().unwrap().name(), "ZDR055");
+        assert_eq!(metadata.sources().len(), 2);
+        let tracks = read.into_tracks();
+        assert_eq!(tracks[0].name(), Some("20250720_124810_F"));
+        assert_eq!(tracks[0].sources().len(), 2);
+        let points = tracks[0].points();
+        assert_eq!(points[0].ele(), Some(12.5));
+        assert_eq!(points[0].event(), Some("Normal"));
+        assert_eq!(points[0].acceleration(), Some([0.01, -0.02, 1.0]));
+        assert_eq!(points[0].voltage(), Some(12.3));
+        assert_eq!(points[1].ele(), None);
+        assert!(points[1].is_synthesized());
+        assert_eq!(points[1].speed(), 42.5);
+    }
+
+    #[test]
+    fn reads_gpx_1_0() {
+        let s = r#"<?xml version="1.0" encoding="UTF-8"?>
+<g:gpx xmlns:g="http://www.topografix.com/GPX/1/0" version="1.0">
+  <g:name>Drive &amp; Park</g:name>
+  <g:trk>
+    <g:name>&#x6771;&#x4EAC;</g:name>
+    <g:trkseg>
+      <g:trkpt lat="35.0" lon="139.0"><g:time>2025-07-20T03:48:10Z</g:time><g:speed>10</g:speed></g:trkpt>
+    </g:trkseg>
+    <g:trkseg>
+      <g:trkpt lat="35.1" lon="139.1"><g:ele>5</g:ele><g:time>2025-07-20T03:58:10Z</g:time></g:trkpt>
+    </g:trkseg>
+  </g:trk>
+</g:gpx>"#;
+        let gpx = GPX::from_str(s).unwrap();
+        assert!(gpx.to_str().contains("<name>Drive &amp; Park</name>"));
+        let tracks = gpx.into_tracks();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].name(), Some("東京"));
+
+        // <trkseg> の区切りは保持せず、1つのログとして読む
+        let points = tracks[0].points();
+        assert_eq!(points.len(), 2);
+        assert!((points[0].speed() - 36.0).abs() < 1e-9);
+        assert_eq!(points[0].ele(), None);
+        assert_eq!(
+            points[0].timestamp().unwrap().to_rfc3339(),
+            "2025-07-20T12:48:10+09:00"
+        );
+        assert_eq!(points[1].ele(), Some(5.0));
+        assert_eq!(points[1].speed(), 0.0);
+    }
+
+    #[test]
+    fn rejects_non_gpx() {
+        assert!(GPX::from_str("<kml></kml>").is_err());
+        assert!(GPX::from_str("<gpx><trk><trkpt lat=\"x\" lon=\"1\"/></trk></gpx>").is_err());
+    }
 }
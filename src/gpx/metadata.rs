@@ -0,0 +1,135 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::{Asia::Tokyo, Tz};
+
+use super::escape_xml;
+
+// 独自拡張要素の名前空間
+pub(crate) const ZDR_NAMESPACE: &str = "https://github.com/misodengaku/zdr055_gpx";
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GPXDevice {
+    name: String,
+    firmware_version: String,
+}
+
+impl GPXDevice {
+    pub(crate) fn new(name: &str, firmware_version: &str) -> Self {
+        GPXDevice {
+            name: name.to_string(),
+            firmware_version: firmware_version.to_string(),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn firmware_version(&self) -> &str {
+        &self.firmware_version
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct GPXBounds {
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    max_lon: f64,
+}
+
+impl GPXBounds {
+    pub(crate) fn new(lat: f64, lon: f64) -> Self {
+        GPXBounds {
+            min_lat: lat,
+            min_lon: lon,
+            max_lat: lat,
+            max_lon: lon,
+        }
+    }
+
+    pub(crate) fn extend(&mut self, other: &GPXBounds) {
+        self.min_lat = self.min_lat.min(other.min_lat);
+        self.min_lon = self.min_lon.min(other.min_lon);
+        self.max_lat = self.max_lat.max(other.max_lat);
+        self.max_lon = self.max_lon.max(other.max_lon);
+    }
+
+    pub(crate) fn to_gpx_string(&self) -> String {
+        format!(
+            "<bounds minlat=\"{:.7}\" minlon=\"{:.7}\" maxlat=\"{:.7}\" maxlon=\"{:.7}\"/>",
+            self.min_lat, self.min_lon, self.max_lat, self.max_lon
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct GPXMetadata {
+    name: String,
+    desc: String,
+    time: DateTime<Tz>,
+    device: Option<GPXDevice>,
+    sources: Vec<String>,
+}
+
+impl GPXMetadata {
+    pub(crate) fn new(name: &str, desc: &str) -> Self {
+        GPXMetadata {
+            name: name.to_string(),
+            desc: desc.to_string(),
+            time: Utc::now().with_timezone(&Tokyo),
+            device: None,
+            sources: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_time(&mut self, time: DateTime<Tz>) {
+        self.time = time;
+    }
+
+    pub(crate) fn set_device(&mut self, device: GPXDevice) {
+        self.device = Some(device);
+    }
+
+    pub(crate) fn device(&self) -> Option<&GPXDevice> {
+        self.device.as_ref()
+    }
+
+    pub(crate) fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    pub(crate) fn add_source(&mut self, source: &str) {
+        self.sources.push(source.to_string());
+    }
+
+    pub(crate) fn to_gpx_string(&self, bounds: Option<GPXBounds>) -> String {
+        let mut extensions = String::new();
+        if let Some(device) = &self.device {
+            extensions.push_str(&format!(
+                "<zdr:device>{}</zdr:device><zdr:firmware_version>{}</zdr:firmware_version>",
+                escape_xml(device.name()),
+                escape_xml(device.firmware_version())
+            ));
+        }
+        if !self.sources.is_empty() {
+            let sources = self
+                .sources
+                .iter()
+                .map(|source| format!("<zdr:source>{}</zdr:source>", escape_xml(source)))
+                .collect::<Vec<String>>();
+            extensions.push_str(&format!("<zdr:sources>{}</zdr:sources>", sources.join("")));
+        }
+        if !extensions.is_empty() {
+            extensions = format!("<extensions>{}</extensions>", extensions);
+        }
+
+        format!(
+            "<metadata><name>{}</name><desc>{}</desc><time>{}</time>{}{}</metadata>\n",
+            escape_xml(&self.name),
+            escape_xml(&self.desc),
+            self.time.format("%Y-%m-%dT%H:%M:%S%:z"),
+            bounds.map(|b| b.to_gpx_string()).unwrap_or_default(),
+            extensions
+        )
+    }
+}
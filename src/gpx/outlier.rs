@@ -0,0 +1,193 @@
+use super::track_point::GPXTrackPoint;
+
+// GPS の測位誤差として許す距離 (m)
+const POSITION_MARGIN: f64 = 30.0;
+// 連続してこの数だけ外れ値と判定したら、基準にしている点の方が誤りだったとみなす
+const MAX_CONSECUTIVE_REJECTS: usize = 3;
+
+// 直前に採用した点と次の点と比べて、ありえない移動をしている点を弾く
+#[derive(Debug)]
+pub(crate) struct OutlierFilter {
+    // km/h
+    max_speed: f64,
+    // m/s^2
+    max_acceleration: f64,
+}
+
+impl OutlierFilter {
+    pub(crate) fn new(max_speed: f64, max_acceleration: f64) -> Self {
+        OutlierFilter {
+            max_speed,
+            max_acceleration,
+        }
+    }
+
+    // 点ごとに、採用する場合は None、弾く場合は理由を返す。
+    // 直前に採用した点 A から点 B への移動がありえなくても、 B から次の点 C への移動が自然なら
+    // A の方が誤っていたか本当に飛んだ (トンネルの出口など) とみなして B を採用する。
+    // 先頭の点は直前の点が無いので、次の2点が互いに矛盾せず先頭の点とだけ矛盾するなら弾く
+    pub(crate) fn reject_reasons(&self, points: &[GPXTrackPoint]) -> Vec<Option<String>> {
+        let mut reasons = Vec::with_capacity(points.len());
+        let mut last: Option<&GPXTrackPoint> = None;
+        let mut consecutive_rejects = 0;
+        for (i, point) in points.iter().enumerate() {
+            let reason = match last {
+                Some(last) => {
+                    self.reject_reason(last, point)
+                        .and_then(|reason| match points.get(i + 1) {
+                            Some(next) if self.reject_reason(point, next).is_none() => None,
+                            _ => Some(reason),
+                        })
+                }
+                None if i == 0 => match (points.get(1), points.get(2)) {
+                    (Some(next), Some(after_next))
+                        if self.reject_reason(next, after_next).is_none() =>
+                    {
+                        self.reject_reason(point, next)
+                    }
+                    _ => None,
+                },
+                None => None,
+            };
+            match reason {
+                Some(reason) if consecutive_rejects < MAX_CONSECUTIVE_REJECTS => {
+                    consecutive_rejects += 1;
+                    reasons.push(Some(reason));
+                }
+                _ => {
+                    consecutive_rejects = 0;
+                    last = Some(point);
+                    reasons.push(None);
+                }
+            }
+        }
+        reasons
+    }
+
+    fn reject_reason(&self, last: &GPXTrackPoint, point: &GPXTrackPoint) -> Option<String> {
+        let dt = match (last.timestamp(), point.timestamp()) {
+            (Ok(last_time), Ok(time)) => (time - last_time).num_milliseconds() as f64 / 1000.0,
+            _ => return None,
+        };
+        if dt <= 0.0 {
+            return None;
+        }
+        let distance = last.distance_to(point);
+
+        // 移動距離から求めた速度そのものがありえない
+        let implied_speed = distance / dt * 3.6;
+        if distance > POSITION_MARGIN && implied_speed > self.max_speed {
+            return Some(format!(
+                "jumped {:.0} m in {:.0} s ({:.0} km/h)",
+                distance, dt, implied_speed
+            ));
+        }
+
+        // 記録された速度から最大加速度で加速しても届かない
+        let reported_speed = last.speed().max(point.speed()) / 3.6;
+        let reachable =
+            reported_speed * dt + self.max_acceleration * dt * dt / 2.0 + POSITION_MARGIN;
+        if distance > reachable {
+            return Some(format!(
+                "moved {:.0} m in {:.0} s while reporting {:.0} km/h (reachable {:.0} m)",
+                distance,
+                dt,
+                reported_speed * 3.6,
+                reachable
+            ));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, second: usize, speed: f64) -> GPXTrackPoint {
+        GPXTrackPoint::new(
+            lat,
+            139.0,
+            None,
+            speed,
+            format!("2025-07-20 12:00:{:02}", second),
+        )
+    }
+
+    fn rejected(points: &[GPXTrackPoint]) -> Vec<bool> {
+        OutlierFilter::new(250.0, 10.0)
+            .reject_reasons(points)
+            .iter()
+            .map(|reason| reason.is_some())
+            .collect()
+    }
+
+    // 緯度 0.0001 度はおよそ 11 m
+    #[test]
+    fn drops_single_sample_teleport() {
+        let points = [
+            point(35.0, 0, 40.0),
+            point(35.0001, 1, 40.0),
+            point(35.0051, 2, 40.0),
+            point(35.0003, 3, 40.0),
+        ];
+        assert_eq!(rejected(&points), vec![false, false, true, false]);
+    }
+
+    #[test]
+    fn drops_movement_faster_than_reported_speed() {
+        // 停止中と記録されているのに 1 秒で約 55 m
+        let points = [
+            point(35.0, 0, 0.0),
+            point(35.0005, 1, 0.0),
+            point(35.0, 2, 0.0),
+        ];
+        assert_eq!(rejected(&points), vec![false, true, false]);
+    }
+
+    #[test]
+    fn recovers_when_next_point_agrees() {
+        // 最初の点の方が外れていた場合は、次の点と矛盾しない2点目から採用する
+        let points = [
+            point(35.1, 0, 40.0),
+            point(35.0, 1, 40.0),
+            point(35.0001, 2, 40.0),
+            point(35.0002, 3, 40.0),
+        ];
+        assert_eq!(rejected(&points), vec![true, false, false, false]);
+
+        // 2点目と3点目も食い違っていれば先頭の点が正しいか分からないので残す
+        let points = [
+            point(35.1, 0, 40.0),
+            point(35.0, 1, 40.0),
+            point(35.2, 2, 40.0),
+        ];
+        assert_eq!(rejected(&points), vec![false, true, true]);
+
+        // トンネルを抜けて位置が飛んだ場合も、飛んだ先の点が続いていれば残す
+        let points = [
+            point(35.0, 0, 40.0),
+            point(35.0001, 1, 40.0),
+            point(35.01, 2, 40.0),
+            point(35.0101, 3, 40.0),
+        ];
+        assert_eq!(rejected(&points), vec![false, false, false, false]);
+    }
+
+    #[test]
+    fn accepts_after_consecutive_rejects() {
+        // 外れた点が続いても MAX_CONSECUTIVE_REJECTS 個までしか弾かない
+        let points = [
+            point(35.0, 0, 40.0),
+            point(35.1, 1, 40.0),
+            point(35.2, 2, 40.0),
+            point(35.3, 3, 40.0),
+            point(35.4, 4, 40.0),
+            point(35.0001, 5, 40.0),
+        ];
+        assert_eq!(
+            rejected(&points),
+            vec![false, true, true, true, false, true]
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone};
+use chrono_tz::{Asia::Tokyo, Tz};
+
+// GPX を読むための最小限の XML トークナイザ
+#[derive(Debug, PartialEq)]
+pub(crate) enum XMLToken {
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    End {
+        name: String,
+    },
+    Text(String),
+}
+
+pub(crate) struct XMLReader<'a> {
+    s: &'a str,
+}
+
+impl<'a> XMLReader<'a> {
+    pub(crate) fn new(s: &'a str) -> Self {
+        XMLReader { s }
+    }
+
+    fn skip_until(&mut self, pattern: &str) -> Result<&'a str, String> {
+        let end = self
+            .s
+            .find(pattern)
+            .ok_or(format!("Missing \"{}\"", pattern))?;
+        let skipped = &self.s[..end];
+        self.s = &self.s[end + pattern.len()..];
+        Ok(skipped)
+    }
+
+    pub(crate) fn next_token(&mut self) -> Result<Option<XMLToken>, String> {
+        loop {
+            if self.s.is_empty() {
+                return Ok(None);
+            }
+            if !self.s.starts_with('<') {
+                let end = self.s.find('<').unwrap_or(self.s.len());
+                let text = &self.s[..end];
+                self.s = &self.s[end..];
+                if text.trim().is_empty() {
+                    continue;
+                }
+                return Ok(Some(XMLToken::Text(unescape_xml(text)?)));
+            }
+
+            if self.s.starts_with("<?") {
+                self.skip_until("?>")?;
+            } else if self.s.starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else if self.s.starts_with("<![CDATA[") {
+                self.s = &self.s["<![CDATA[".len()..];
+                let text = self.skip_until("]]>")?;
+                return Ok(Some(XMLToken::Text(text.to_string())));
+            } else if self.s.starts_with("<!") {
+                self.skip_until(">")?;
+            } else if self.s.starts_with("</") {
+                self.s = &self.s[2..];
+                let name = self.skip_until(">")?.trim();
+                return Ok(Some(XMLToken::End {
+                    name: local_name(name).to_string(),
+                }));
+            } else {
+                self.s = &self.s[1..];
+                return self.read_start_tag().map(Some);
+            }
+        }
+    }
+
+    fn read_start_tag(&mut self) -> Result<XMLToken, String> {
+        let name_end = self
+            .s
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .ok_or("Unterminated tag")?;
+        let name = local_name(&self.s[..name_end]).to_string();
+        self.s = &self.s[name_end..];
+
+        let mut attributes = Vec::new();
+        loop {
+            self.s = self.s.trim_start();
+            if let Some(rest) = self.s.strip_prefix("/>") {
+                self.s = rest;
+                return Ok(XMLToken::Start {
+                    name,
+                    attributes,
+                    self_closing: true,
+                });
+            }
+            if let Some(rest) = self.s.strip_prefix('>') {
+                self.s = rest;
+                return Ok(XMLToken::Start {
+                    name,
+                    attributes,
+                    self_closing: false,
+                });
+            }
+
+            let (key, rest) = self
+                .s
+                .split_once('=')
+                .ok_or(format!("Invalid attribute in <{}>", name))?;
+            let rest = rest.trim_start();
+            let quote = rest
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')
+                .ok_or(format!("Unquoted attribute in <{}>", name))?;
+            let (value, rest) = rest[1..]
+                .split_once(quote)
+                .ok_or(format!("Unterminated attribute in <{}>", name))?;
+            attributes.push((local_name(key.trim()).to_string(), unescape_xml(value)?));
+            self.s = rest;
+        }
+    }
+}
+
+// 名前空間プレフィックスを取り除く (zdr:device -> device)
+fn local_name(name: &str) -> &str {
+    name.rsplit_once(':')
+        .map(|(_, local)| local)
+        .unwrap_or(name)
+}
+
+pub(crate) fn unescape_xml(s: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let (entity, after) = rest[start + 1..]
+            .split_once(';')
+            .ok_or("Unterminated entity")?;
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse::<u32>().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+                    .ok_or(format!("Unknown entity: &{};", entity))?
+            }
+        };
+        result.push(c);
+        rest = after;
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+// ISO 8601 の時刻を GPXTrackPoint の内部表現 (JST の "YYYY-MM-DD HH:MM:SS") に変換する。
+// xsd:dateTime では時差を省略できるので、その場合は timezone の時刻とみなす
+pub(crate) fn parse_gpx_time(s: &str, timezone: Tz) -> Result<String, String> {
+    let time = match DateTime::parse_from_rfc3339(s.trim()) {
+        Ok(time) => time.with_timezone(&Tokyo),
+        Err(e) => NaiveDateTime::parse_from_str(s.trim(), "%Y-%m-%dT%H:%M:%S%.f")
+            .ok()
+            .and_then(|time| timezone.from_local_datetime(&time).earliest())
+            .ok_or(format!("Failed to parse time {}: {}", s, e))?
+            .with_timezone(&Tokyo),
+    };
+    Ok(time.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+// 自前で出力した <desc>12.34 km/h</desc> から速度を取り出す
+pub(crate) fn parse_speed_desc(s: &str) -> Option<f64> {
+    s.trim().strip_suffix("km/h")?.trim().parse::<f64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescapes_entities() {
+        assert_eq!(
+            unescape_xml("A &amp; B &lt;&gt; &quot;&apos; &#65;&#x3042;").unwrap(),
+            "A & B <> \"' Aあ"
+        );
+        assert!(unescape_xml("&unknown;").is_err());
+        assert!(unescape_xml("&amp").is_err());
+    }
+
+    #[test]
+    fn strips_namespace_prefixes() {
+        let mut reader = XMLReader::new(
+            "<?xml version=\"1.0\"?><!-- comment --><g:trkpt g:lat='35.0' lon=\"139.0\"><![CDATA[a<b]]></g:trkpt><zdr:x/>",
+        );
+        assert_eq!(
+            reader.next_token().unwrap(),
+            Some(XMLToken::Start {
+                name: "trkpt".to_string(),
+                attributes: vec![
+                    ("lat".to_string(), "35.0".to_string()),
+                    ("lon".to_string(), "139.0".to_string())
+                ],
+                self_closing: false,
+            })
+        );
+        assert_eq!(
+            reader.next_token().unwrap(),
+            Some(XMLToken::Text("a<b".to_string()))
+        );
+        assert_eq!(
+            reader.next_token().unwrap(),
+            Some(XMLToken::End {
+                name: "trkpt".to_string()
+            })
+        );
+        assert_eq!(
+            reader.next_token().unwrap(),
+            Some(XMLToken::Start {
+                name: "x".to_string(),
+                attributes: Vec::new(),
+                self_closing: true,
+            })
+        );
+        assert_eq!(reader.next_token().unwrap(), None);
+    }
+
+    #[test]
+    fn converts_time_to_internal_format() {
+        assert_eq!(
+            parse_gpx_time("2025-07-20T03:48:10Z", Tokyo).unwrap(),
+            "2025-07-20 12:48:10"
+        );
+        assert_eq!(
+            parse_gpx_time("2025-07-20T12:48:10+09:00", chrono_tz::UTC).unwrap(),
+            "2025-07-20 12:48:10"
+        );
+        assert!(parse_gpx_time("2025-07-20 12:48:10", Tokyo).is_err());
+        // 時差の無い時刻は指定したタイムゾーンの時刻として読む
+        assert_eq!(
+            parse_gpx_time("2025-07-20T03:48:10.500", chrono_tz::UTC).unwrap(),
+            "2025-07-20 12:48:10"
+        );
+        assert_eq!(
+            parse_gpx_time("2025-07-20T12:48:10", Tokyo).unwrap(),
+            "2025-07-20 12:48:10"
+        );
+        assert_eq!(parse_speed_desc("12.34 km/h"), Some(12.34));
+        assert_eq!(parse_speed_desc("fast"), None);
+    }
+}
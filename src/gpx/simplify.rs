@@ -0,0 +1,152 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::track_point::{GPXTrackPoint, LocalProjection};
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum SimplifyAlgorithm {
+    // 許容値は線からの距離 (m)
+    DouglasPeucker,
+    // 許容値 (m) の2乗を三角形の面積 (m^2) の閾値とする
+    Visvalingam,
+}
+
+// 点 p から線分 ab までの距離
+fn segment_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length2 = dx * dx + dy * dy;
+    let t = if length2 > 0.0 {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (x, y) = (a.0 + dx * t, a.1 + dy * t);
+    ((p.0 - x).powi(2) + (p.1 - y).powi(2)).sqrt()
+}
+
+fn triangle_area(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() / 2.0
+}
+
+// points の両端は必ず残し、それ以外に残す点を true にして返す
+pub(crate) fn simplify(
+    points: &[GPXTrackPoint],
+    algorithm: SimplifyAlgorithm,
+    tolerance: f64,
+) -> Vec<bool> {
+    let mut keep = vec![false; points.len()];
+    if points.len() <= 2 {
+        keep.fill(true);
+        return keep;
+    }
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    let projection = LocalProjection::new(&points[0]);
+    let projected = points
+        .iter()
+        .map(|point| projection.project(point))
+        .collect::<Vec<_>>();
+    match algorithm {
+        SimplifyAlgorithm::DouglasPeucker => douglas_peucker(&projected, tolerance, &mut keep),
+        SimplifyAlgorithm::Visvalingam => visvalingam(&projected, tolerance * tolerance, &mut keep),
+    }
+    keep
+}
+
+fn douglas_peucker(points: &[(f64, f64)], tolerance: f64, keep: &mut [bool]) {
+    // 1日分の点でも再帰が深くならないようスタックで処理する
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = stack.pop() {
+        let farthest = (start + 1..end)
+            .map(|i| (i, segment_distance(points[i], points[start], points[end])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                stack.push((start, i));
+                stack.push((i, end));
+            }
+        }
+    }
+}
+
+// 面積の小さい順に取り出すためのヒープの要素
+struct Vertex {
+    area: f64,
+    index: usize,
+}
+
+impl PartialEq for Vertex {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Vertex {}
+
+impl PartialOrd for Vertex {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Vertex {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .area
+            .total_cmp(&self.area)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+fn visvalingam(points: &[(f64, f64)], min_area: f64, keep: &mut [bool]) {
+    let n = points.len();
+    let mut prev = (0..n).map(|i| i.wrapping_sub(1)).collect::<Vec<usize>>();
+    let mut next = (1..=n).collect::<Vec<usize>>();
+    let mut areas = vec![f64::INFINITY; n];
+    let mut heap = BinaryHeap::new();
+    for i in 1..n - 1 {
+        areas[i] = triangle_area(points[i - 1], points[i], points[i + 1]);
+        heap.push(Vertex {
+            area: areas[i],
+            index: i,
+        });
+    }
+
+    let mut removed = vec![false; n];
+    while let Some(Vertex { area, index }) = heap.pop() {
+        // 面積が更新された古い要素は読み飛ばす
+        if removed[index] || area != areas[index] {
+            continue;
+        }
+        if area >= min_area {
+            break;
+        }
+        removed[index] = true;
+        let (p, q) = (prev[index], next[index]);
+        next[p] = q;
+        prev[q] = p;
+        // 削除した点より小さい面積にはしない (先に消えるべき点が後に残らないように)
+        for neighbor in [p, q] {
+            if neighbor == 0 || neighbor == n - 1 {
+                continue;
+            }
+            let new_area = triangle_area(
+                points[prev[neighbor]],
+                points[neighbor],
+                points[next[neighbor]],
+            )
+            .max(area);
+            areas[neighbor] = new_area;
+            heap.push(Vertex {
+                area: new_area,
+                index: neighbor,
+            });
+        }
+    }
+
+    for i in 1..n - 1 {
+        keep[i] = !removed[i];
+    }
+}
@@ -0,0 +1,265 @@
+use chrono::Duration;
+
+use super::track_point::{GPXTrackPoint, LocalProjection};
+
+// 加速度の揺らぎ (m/s^2)。大きいほど観測した位置に追従する
+const ACCELERATION_NOISE: f64 = 1.0;
+// 記録された速度の誤差 (m/s)
+const SPEED_NOISE: f64 = 1.0;
+// 向きを決めるのに使う前後の点の距離は測位誤差のこの倍数以上にする
+const HEADING_DISTANCE_RATIO: f64 = 10.0;
+// 向きを決めるのに前後何点まで遡るか
+const MAX_HEADING_SPAN: usize = 10;
+
+type Vector = [f64; 2];
+type Matrix = [[f64; 2]; 2];
+
+// 状態 (位置, 速度) を dt 秒進める
+fn predict(x: Vector, p: Matrix, dt: f64) -> (Vector, Matrix) {
+    let q = ACCELERATION_NOISE * ACCELERATION_NOISE;
+    let x = [x[0] + x[1] * dt, x[1]];
+    // F P F^T + Q
+    let p00 = p[0][0] + dt * (p[0][1] + p[1][0]) + dt * dt * p[1][1];
+    let p01 = p[0][1] + dt * p[1][1];
+    let p10 = p[1][0] + dt * p[1][1];
+    let p = [
+        [p00 + q * dt.powi(3) / 3.0, p01 + q * dt.powi(2) / 2.0],
+        [p10 + q * dt.powi(2) / 2.0, p[1][1] + q * dt],
+    ];
+    (x, p)
+}
+
+// 状態の k 番目の成分を分散 r で z と観測した
+fn update(x: Vector, p: Matrix, k: usize, z: f64, r: f64) -> (Vector, Matrix) {
+    let s = p[k][k] + r;
+    let gain = [p[0][k] / s, p[1][k] / s];
+    let residual = z - x[k];
+    let x = [x[0] + gain[0] * residual, x[1] + gain[1] * residual];
+    let p = [
+        [p[0][0] - gain[0] * p[k][0], p[0][1] - gain[0] * p[k][1]],
+        [p[1][0] - gain[1] * p[k][0], p[1][1] - gain[1] * p[k][1]],
+    ];
+    (x, p)
+}
+
+// 1軸ぶんの前向きのフィルタと RTS による後ろ向きの平滑化
+fn smooth_axis(
+    times: &[f64],
+    positions: &[f64],
+    velocities: &[Option<f64>],
+    position_noise: f64,
+) -> Vec<f64> {
+    let n = positions.len();
+    let r = position_noise * position_noise;
+    let mut predicted: Vec<(Vector, Matrix)> = Vec::with_capacity(n);
+    let mut filtered: Vec<(Vector, Matrix)> = Vec::with_capacity(n);
+    for i in 0..n {
+        let (x, p) = match filtered.last() {
+            Some((x, p)) => predict(*x, *p, times[i] - times[i - 1]),
+            // 速度は分からないので大きな分散から始める
+            None => ([positions[0], 0.0], [[r, 0.0], [0.0, 100.0]]),
+        };
+        predicted.push((x, p));
+        let (x, p) = update(x, p, 0, positions[i], r);
+        let (x, p) = match velocities[i] {
+            Some(v) => update(x, p, 1, v, SPEED_NOISE * SPEED_NOISE),
+            None => (x, p),
+        };
+        filtered.push((x, p));
+    }
+
+    let mut smoothed = vec![0.0; n];
+    let mut next = filtered[n - 1].0;
+    smoothed[n - 1] = next[0];
+    for i in (0..n - 1).rev() {
+        let dt = times[i + 1] - times[i];
+        let (x, p) = filtered[i];
+        let (xp, pp) = predicted[i + 1];
+        // C = P F^T Pp^-1
+        let pf = [
+            [p[0][0] + dt * p[0][1], p[0][1]],
+            [p[1][0] + dt * p[1][1], p[1][1]],
+        ];
+        let det = pp[0][0] * pp[1][1] - pp[0][1] * pp[1][0];
+        let inv = [
+            [pp[1][1] / det, -pp[0][1] / det],
+            [-pp[1][0] / det, pp[0][0] / det],
+        ];
+        let c = [
+            [
+                pf[0][0] * inv[0][0] + pf[0][1] * inv[1][0],
+                pf[0][0] * inv[0][1] + pf[0][1] * inv[1][1],
+            ],
+            [
+                pf[1][0] * inv[0][0] + pf[1][1] * inv[1][0],
+                pf[1][0] * inv[0][1] + pf[1][1] * inv[1][1],
+            ],
+        ];
+        let diff = [next[0] - xp[0], next[1] - xp[1]];
+        next = [
+            x[0] + c[0][0] * diff[0] + c[0][1] * diff[1],
+            x[1] + c[1][0] * diff[0] + c[1][1] * diff[1],
+        ];
+        smoothed[i] = next[0];
+    }
+    smoothed
+}
+
+// 等速度モデルのカルマンフィルタで平滑化した (緯度, 経度) を返す。
+// 観測値は測位した位置と、記録された速度を前後の点を結ぶ向きに合わせたもの
+pub(crate) fn kalman_smooth(points: &[GPXTrackPoint], position_noise: f64) -> Vec<(f64, f64)> {
+    if points.len() < 2 {
+        return points
+            .iter()
+            .map(|point| (point.lat(), point.lon()))
+            .collect();
+    }
+    let projection = LocalProjection::new(&points[0]);
+    let projected = points
+        .iter()
+        .map(|point| projection.project(point))
+        .collect::<Vec<_>>();
+    let start = points[0].timestamp().ok();
+    let times = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| match (start, point.timestamp()) {
+            (Some(start), Ok(time)) => (time - start).num_milliseconds() as f64 / 1000.0,
+            // 時刻が読めなければ 1 秒間隔とみなす
+            _ => i as f64,
+        })
+        .collect::<Vec<f64>>();
+
+    let mut velocities = [Vec::new(), Vec::new()];
+    for i in 0..points.len() {
+        let speed = points[i].speed() / 3.6;
+        // 測位誤差に比べて十分離れるまで前後に広げた2点を結ぶ向きを使う
+        let mut heading = (0.0, 0.0, 0.0);
+        for span in 1..=MAX_HEADING_SPAN {
+            let prev = projected[i.saturating_sub(span)];
+            let next = projected[(i + span).min(points.len() - 1)];
+            let (dx, dy) = (next.0 - prev.0, next.1 - prev.1);
+            heading = (dx, dy, (dx * dx + dy * dy).sqrt());
+            if heading.2 >= position_noise * HEADING_DISTANCE_RATIO {
+                break;
+            }
+        }
+        let (dx, dy, length) = heading;
+        let velocity = if points[i].speed() < 1.0 {
+            Some((0.0, 0.0))
+        } else if length >= position_noise * HEADING_DISTANCE_RATIO / 2.0 {
+            Some((speed * dx / length, speed * dy / length))
+        } else {
+            None
+        };
+        velocities[0].push(velocity.map(|v| v.0));
+        velocities[1].push(velocity.map(|v| v.1));
+    }
+
+    let xs = projected.iter().map(|p| p.0).collect::<Vec<f64>>();
+    let ys = projected.iter().map(|p| p.1).collect::<Vec<f64>>();
+    let xs = smooth_axis(&times, &xs, &velocities[0], position_noise);
+    let ys = smooth_axis(&times, &ys, &velocities[1], position_noise);
+
+    xs.into_iter()
+        .zip(ys)
+        .map(|position| projection.unproject(position))
+        .collect()
+}
+
+// a と b の間が 2 秒以上 max_gap 以下空いていれば、1 秒ごとに直線で補間した点を返す
+pub(crate) fn interpolate(
+    a: &GPXTrackPoint,
+    b: &GPXTrackPoint,
+    max_gap: std::time::Duration,
+) -> Vec<GPXTrackPoint> {
+    let (Ok(start), Ok(end)) = (a.timestamp(), b.timestamp()) else {
+        return Vec::new();
+    };
+    let seconds = (end - start).num_seconds();
+    if seconds < 2 || seconds as u64 > max_gap.as_secs() {
+        return Vec::new();
+    }
+    (1..seconds)
+        .map(|second| {
+            let t = second as f64 / seconds as f64;
+            let lerp = |from: f64, to: f64| from + (to - from) * t;
+            let ele = match (a.ele(), b.ele()) {
+                (Some(from), Some(to)) => Some(lerp(from, to)),
+                _ => None,
+            };
+            let mut point = GPXTrackPoint::new(
+                lerp(a.lat(), b.lat()),
+                lerp(a.lon(), b.lon()),
+                ele,
+                lerp(a.speed(), b.speed()),
+                (start + Duration::seconds(second))
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+            );
+            // イベント種別の変化点が増えないよう直前の点に合わせる
+            point.set_event(a.event().map(|event| event.to_string()));
+            point.set_synthesized(true);
+            point
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, lon: f64, second: usize, speed: f64) -> GPXTrackPoint {
+        GPXTrackPoint::new(
+            lat,
+            lon,
+            None,
+            speed,
+            format!("2025-07-20 12:{:02}:{:02}", second / 60, second % 60),
+        )
+    }
+
+    #[test]
+    fn reduces_jitter() {
+        // 東へ 10 m/s で進むログに ±5 m 程度の揺れを加える
+        let truth = (0..120)
+            .map(|i| point(35.0, 139.0 + i as f64 * 0.00011, i, 36.0))
+            .collect::<Vec<_>>();
+        let noisy = truth
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let offset = ((i * 7919) % 11) as f64 - 5.0;
+                point(p.lat() + offset * 0.000009, p.lon(), i, 36.0)
+            })
+            .collect::<Vec<_>>();
+        let smoothed = kalman_smooth(&noisy, 5.0);
+        let error = |points: Vec<(f64, f64)>| {
+            points
+                .iter()
+                .zip(&truth)
+                .map(|((lat, lon), t)| t.distance_to(&point(*lat, *lon, 0, 0.0)))
+                .sum::<f64>()
+                / truth.len() as f64
+        };
+        let raw = error(noisy.iter().map(|p| (p.lat(), p.lon())).collect());
+        let smoothed = error(smoothed);
+        assert!(smoothed < raw / 2.0, "raw {} smoothed {}", raw, smoothed);
+    }
+
+    #[test]
+    fn interpolates_short_gaps() {
+        let a = point(35.0, 139.0, 0, 30.0);
+        let b = point(35.0004, 139.0, 4, 50.0);
+        let points = interpolate(&a, &b, std::time::Duration::from_secs(10));
+        assert_eq!(points.len(), 3);
+        assert!((points[1].lat() - 35.0002).abs() < 1e-9);
+        assert!((points[1].speed() - 40.0).abs() < 1e-9);
+        assert!(points.iter().all(|p| p.is_synthesized()));
+        assert_eq!(
+            points[2].timestamp().unwrap().format("%M:%S").to_string(),
+            "00:03"
+        );
+        assert!(interpolate(&a, &b, std::time::Duration::from_secs(3)).is_empty());
+    }
+}
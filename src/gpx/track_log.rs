@@ -1,16 +1,164 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+use super::escape_xml;
+use super::metadata::{GPXBounds, GPXDevice};
+use super::simplify::{simplify, SimplifyAlgorithm};
+use super::smooth::{interpolate, kalman_smooth};
 use super::track_point::GPXTrackPoint;
 
+// 連続する2点の間隔がこれを超えたら <trkseg> を分割する
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GPXSegmentGap {
+    time: Option<std::time::Duration>,
+    distance: Option<f64>,
+}
+
+impl GPXSegmentGap {
+    pub(crate) fn new(time: Option<std::time::Duration>, distance: Option<f64>) -> Self {
+        GPXSegmentGap { time, distance }
+    }
+
+    pub(crate) fn is_gap(&self, prev: &GPXTrackPoint, next: &GPXTrackPoint) -> bool {
+        if let Some(max_distance) = self.distance {
+            if prev.distance_to(next) > max_distance {
+                return true;
+            }
+        }
+        if let Some(max_time) = self.time {
+            if let (Ok(prev_time), Ok(next_time)) = (prev.timestamp(), next.timestamp()) {
+                // 時刻が巻き戻っている場合も別区間として扱う
+                return match (next_time - prev_time).to_std() {
+                    Ok(diff) => diff > max_time,
+                    Err(_) => true,
+                };
+            }
+        }
+        false
+    }
+}
+
+// 点列を順にたどった距離 (m)
+// 空の sum() は -0.0 になるので 0.0 から足す
+pub(crate) fn path_distance(points: &[GPXTrackPoint]) -> f64 {
+    points
+        .windows(2)
+        .fold(0.0, |distance, w| distance + w[0].distance_to(&w[1]))
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct GPXTrackLog {
+    name: Option<String>,
+    device: Option<GPXDevice>,
+    sources: Vec<String>,
+    // sources の各ファイルから取り込んだ最初の点のインデックス
+    source_starts: Vec<usize>,
     points: Vec<GPXTrackPoint>,
 }
 
 impl GPXTrackLog {
     pub(crate) fn new() -> Self {
-        GPXTrackLog { points: Vec::new() }
+        GPXTrackLog {
+            name: None,
+            device: None,
+            sources: Vec::new(),
+            source_starts: Vec::new(),
+            points: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+
+    pub(crate) fn device(&self) -> Option<&GPXDevice> {
+        self.device.as_ref()
+    }
+
+    pub(crate) fn set_device(&mut self, device: GPXDevice) {
+        self.device = Some(device);
+    }
+
+    // ログの元になった動画ファイル名
+    pub(crate) fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    // 以降に push される点の元ファイルとして登録する
+    pub(crate) fn add_source(&mut self, source: &str) {
+        self.sources.push(source.to_string());
+        self.source_starts.push(self.points.len());
+    }
+
+    // 元ファイルごとに、そのファイルから取り込んだ点の範囲を返す。
+    // 範囲が分からないもの (GPX から読んだトラックなど) は後続の範囲と同じになる
+    pub(crate) fn source_ranges(&self) -> Vec<(&str, std::ops::Range<usize>)> {
+        self.sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                let start = self.source_starts[i];
+                let end = self.source_starts[i + 1..]
+                    .iter()
+                    .find(|next| **next > start)
+                    .copied()
+                    .unwrap_or(self.points.len());
+                (source.as_str(), start..end)
+            })
+            .collect()
+    }
+
+    // 元ファイルごとの点の範囲。範囲が重複するもの・空のものはまとめ、元ファイルが不明なら全体を1つとする
+    pub(crate) fn clip_ranges(&self) -> Vec<std::ops::Range<usize>> {
+        let mut ranges = self
+            .source_ranges()
+            .into_iter()
+            .map(|(_, range)| range)
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.dedup();
+        if ranges.is_empty() && !self.points.is_empty() {
+            ranges.push(0..self.points.len());
+        }
+        ranges
+    }
+
+    // range の点を含む元ファイル名の一覧
+    pub(crate) fn sources_in(&self, range: &std::ops::Range<usize>) -> Vec<&str> {
+        self.source_ranges()
+            .into_iter()
+            .filter(|(_, r)| r.start < range.end && range.start < r.end)
+            .map(|(source, _)| source)
+            .collect()
+    }
+
+    // range の点だけを持つログを返す。元ファイルはその範囲に点があるものだけ残す
+    pub(crate) fn slice(&self, range: std::ops::Range<usize>) -> GPXTrackLog {
+        let mut log = GPXTrackLog {
+            name: self.name.clone(),
+            device: self.device.clone(),
+            sources: Vec::new(),
+            source_starts: Vec::new(),
+            points: self.points[range.clone()].to_vec(),
+        };
+        for (source, source_range) in self.source_ranges() {
+            if source_range.start < range.end && range.start < source_range.end {
+                log.sources.push(source.to_string());
+                log.source_starts
+                    .push(source_range.start.max(range.start) - range.start);
+            }
+        }
+        log
     }
 
     pub(crate) fn extend(&mut self, other: GPXTrackLog) {
+        if self.device.is_none() {
+            self.device = other.device;
+        }
+        let offset = self.points.len();
+        self.sources.extend(other.sources);
+        self.source_starts
+            .extend(other.source_starts.iter().map(|start| start + offset));
         self.points.extend(other.points);
     }
 
@@ -18,6 +166,18 @@ impl GPXTrackLog {
         self.points.push(point);
     }
 
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub(crate) fn points(&self) -> &[GPXTrackPoint] {
+        &self.points
+    }
+
+    pub(crate) fn points_mut(&mut self) -> &mut [GPXTrackPoint] {
+        &mut self.points
+    }
+
     pub(crate) fn first(&self) -> Option<&GPXTrackPoint> {
         self.points.first()
     }
@@ -30,17 +190,331 @@ impl GPXTrackLog {
         self.points.is_empty()
     }
 
-    pub(crate) fn to_str(&self) -> String {
+    pub(crate) fn bounds(&self) -> Option<GPXBounds> {
+        let mut points = self.points.iter();
+        let first = points.next()?;
+        let mut bounds = GPXBounds::new(first.lat(), first.lon());
+        for point in points {
+            bounds.extend(&GPXBounds::new(point.lat(), point.lon()));
+        }
+        Some(bounds)
+    }
+
+    // gap を超える間隔のところで分割した区間の範囲を返す
+    pub(crate) fn segment_ranges(&self, gap: &GPXSegmentGap) -> Vec<std::ops::Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for i in 1..self.points.len() {
+            if gap.is_gap(&self.points[i - 1], &self.points[i]) {
+                ranges.push(start..i);
+                start = i;
+            }
+        }
+        if start < self.points.len() {
+            ranges.push(start..self.points.len());
+        }
+        ranges
+    }
+
+    pub(crate) fn segments(&self, gap: &GPXSegmentGap) -> Vec<&[GPXTrackPoint]> {
+        self.segment_ranges(gap)
+            .into_iter()
+            .map(|range| &self.points[range])
+            .collect()
+    }
+
+    // 点を間引き、削除した点の数を返す。
+    // 区間の両端・元ファイルの先頭・イベント種別が変わる点は必ず残し、その間ごとに間引く
+    pub(crate) fn simplify(
+        &mut self,
+        algorithm: SimplifyAlgorithm,
+        tolerance: f64,
+        gap: &GPXSegmentGap,
+    ) -> usize {
+        let n = self.points.len();
+        let mut keep = vec![false; n];
+        for range in self.segment_ranges(gap) {
+            keep[range.start] = true;
+            keep[range.end - 1] = true;
+        }
+        for start in &self.source_starts {
+            if let Some(keep) = keep.get_mut(*start) {
+                *keep = true;
+            }
+        }
+        for (i, pair) in self.points.windows(2).enumerate() {
+            if pair[0].event() != pair[1].event() {
+                keep[i + 1] = true;
+            }
+        }
+
+        let fixed = (0..n).filter(|i| keep[*i]).collect::<Vec<usize>>();
+        for window in fixed.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            if end - start < 2 {
+                continue;
+            }
+            let kept = simplify(&self.points[start..=end], algorithm, tolerance);
+            for (i, kept) in kept.into_iter().enumerate() {
+                keep[start + i] |= kept;
+            }
+        }
+
+        self.retain_points(keep);
+        n - self.points.len()
+    }
+
+    // keep が false の点を取り除き、元ファイルの開始位置を取り除いた後のインデックスに直す
+    fn retain_points(&mut self, keep: Vec<bool>) {
+        let n = self.points.len();
+        let mut kept_before = vec![0; n + 1];
+        for i in 0..n {
+            kept_before[i + 1] = kept_before[i] + keep[i] as usize;
+        }
+        for start in self.source_starts.iter_mut() {
+            *start = kept_before[(*start).min(n)];
+        }
+        let mut keep = keep.into_iter();
+        self.points.retain(|_| keep.next().unwrap_or(true));
+    }
+
+    // time 以前の時刻の点を取り除き、取り除いた数を返す。時刻の読めない点は残す
+    pub(crate) fn remove_until(&mut self, time: &DateTime<Tz>) -> usize {
+        let n = self.points.len();
+        let keep = self
+            .points
+            .iter()
+            .map(|point| {
+                point
+                    .timestamp()
+                    .map_or(true, |timestamp| timestamp > *time)
+            })
+            .collect::<Vec<bool>>();
+        self.retain_points(keep);
+        n - self.points.len()
+    }
+
+    // 区間ごとにカルマンフィルタで位置を平滑化する。 position_noise は測位誤差 (m)
+    pub(crate) fn smooth(&mut self, position_noise: f64, gap: &GPXSegmentGap) {
+        for range in self.segment_ranges(gap) {
+            let smoothed = kalman_smooth(&self.points[range.clone()], position_noise);
+            for (point, (lat, lon)) in self.points[range].iter_mut().zip(smoothed) {
+                point.set_position(lat, lon);
+            }
+        }
+    }
+
+    // max_gap 以下の抜けを補間した点で埋め、追加した点の数を返す。
+    // 時間で分けた区間の間も max_gap までは埋めるが、距離で分けた区間の間は埋めない
+    pub(crate) fn interpolate_gaps(
+        &mut self,
+        max_gap: std::time::Duration,
+        gap: &GPXSegmentGap,
+    ) -> usize {
+        let n = self.points.len();
+        let mut segment_start = vec![false; n];
+        for range in self.segment_ranges(&GPXSegmentGap::new(None, gap.distance)) {
+            segment_start[range.start] = true;
+        }
+        let mut points = Vec::with_capacity(n);
+        // 元の点が移る先のインデックス
+        let mut new_index = Vec::with_capacity(n + 1);
+        for (i, segment_start) in segment_start.into_iter().enumerate() {
+            if !segment_start {
+                points.extend(interpolate(&self.points[i - 1], &self.points[i], max_gap));
+            }
+            new_index.push(points.len());
+            points.push(self.points[i].clone());
+        }
+        new_index.push(points.len());
+        for start in self.source_starts.iter_mut() {
+            *start = new_index[(*start).min(n)];
+        }
+        self.points = points;
+        self.points.len() - n
+    }
+
+    pub(crate) fn to_str(&self, gap: &GPXSegmentGap) -> String {
         if self.points.is_empty() {
             return String::new();
         }
 
-        let track_points = self
-            .points
+        let name = match &self.name {
+            Some(name) => format!("<name>{}</name>", escape_xml(name)),
+            None => String::new(),
+        };
+        let sources = if self.sources.is_empty() {
+            String::new()
+        } else {
+            let sources = self
+                .sources
+                .iter()
+                .map(|source| format!("<zdr:source>{}</zdr:source>", escape_xml(source)))
+                .collect::<Vec<String>>();
+            format!(
+                "<extensions><zdr:sources>{}</zdr:sources></extensions>",
+                sources.join("")
+            )
+        };
+        let segments = self
+            .segments(gap)
             .iter()
-            .map(|log| log.to_gpx_string())
+            .map(|segment| {
+                let track_points = segment
+                    .iter()
+                    .map(|log| log.to_gpx_string())
+                    .collect::<Vec<String>>();
+                format!("<trkseg>{}</trkseg>", track_points.join(""))
+            })
             .collect::<Vec<String>>();
 
-        format!("<trk><trkseg>{}</trkseg></trk>\n", track_points.join(""))
+        format!("<trk>{}{}{}</trk>\n", name, sources, segments.join(""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, lon: f64, second: usize, event: &str) -> GPXTrackPoint {
+        let mut point = GPXTrackPoint::new(
+            lat,
+            lon,
+            None,
+            40.0,
+            format!("2025-07-20 12:{:02}:{:02}", second / 60, second % 60),
+        );
+        point.set_event(Some(event.to_string()));
+        point
+    }
+
+    // 東へまっすぐ進み、途中で少し北へ曲がるログ
+    fn straight_log() -> GPXTrackLog {
+        let mut log = GPXTrackLog::new();
+        log.add_source("a.AVI");
+        for i in 0..100 {
+            if i == 60 {
+                log.add_source("b.AVI");
+            }
+            let lat = if i < 80 {
+                35.0
+            } else {
+                35.0 + (i - 79) as f64 * 0.0001
+            };
+            let event = if i == 30 { "G" } else { "N" };
+            log.push(point(lat, 139.0 + i as f64 * 0.0001, i, event));
+        }
+        log
+    }
+
+    #[test]
+    fn keeps_fixed_points() {
+        for algorithm in [
+            SimplifyAlgorithm::DouglasPeucker,
+            SimplifyAlgorithm::Visvalingam,
+        ] {
+            let mut log = straight_log();
+            let removed = log.simplify(algorithm, 5.0, &GPXSegmentGap::default());
+            assert_eq!(removed + log.points().len(), 100);
+            let times = log
+                .points()
+                .iter()
+                .map(|p| p.timestamp().unwrap().format("%M:%S").to_string())
+                .collect::<Vec<String>>();
+            // 両端、イベントの前後、元ファイルの先頭、曲がり角
+            for expected in ["00:00", "00:30", "00:31", "01:00", "01:19", "01:39"] {
+                assert!(times.contains(&expected.to_string()), "{:?}", times);
+            }
+            assert!(log.points().len() <= 8, "{:?}", times);
+            let ranges = log.source_ranges();
+            assert_eq!(ranges[1].0, "b.AVI");
+            assert_eq!(
+                log.points()[ranges[1].1.start]
+                    .timestamp()
+                    .unwrap()
+                    .format("%M:%S")
+                    .to_string(),
+                "01:00"
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_segment_boundaries() {
+        let mut log = GPXTrackLog::new();
+        for i in 0..10 {
+            log.push(point(35.0, 139.0 + i as f64 * 0.0001, i, "N"));
+        }
+        for i in 10..20 {
+            log.push(point(35.0, 139.0 + i as f64 * 0.0001, i + 600, "N"));
+        }
+        let gap = GPXSegmentGap::new(Some(std::time::Duration::from_secs(120)), None);
+        log.simplify(SimplifyAlgorithm::DouglasPeucker, 5.0, &gap);
+        assert_eq!(log.points().len(), 4);
+        assert_eq!(log.segments(&gap).len(), 2);
+    }
+
+    #[test]
+    fn removes_overlapping_points() {
+        let mut log = GPXTrackLog::new();
+        log.add_source("a.AVI");
+        for i in 0..5 {
+            log.push(point(35.0, 139.0, i, "N"));
+        }
+        log.add_source("b.AVI");
+        for i in 5..10 {
+            log.push(point(35.0, 139.0, i, "N"));
+        }
+        let time = log.points()[6].timestamp().unwrap();
+        assert_eq!(log.remove_until(&time), 7);
+        assert_eq!(log.points().len(), 3);
+        assert_eq!(log.clip_ranges(), vec![0..3]);
+    }
+
+    #[test]
+    fn interpolates_gaps() {
+        let mut log = GPXTrackLog::new();
+        log.add_source("a.AVI");
+        for i in [0, 1, 5] {
+            log.push(point(35.0, 139.0 + i as f64 * 0.0001, i, "N"));
+        }
+        log.add_source("b.AVI");
+        for i in [6, 300] {
+            log.push(point(35.0, 139.0 + i as f64 * 0.0001, i, "N"));
+        }
+        let gap = GPXSegmentGap::new(Some(std::time::Duration::from_secs(120)), None);
+        let added = log.interpolate_gaps(std::time::Duration::from_secs(30), &gap);
+        assert_eq!(added, 3);
+        assert_eq!(log.points().len(), 8);
+        assert!(log.points()[2].is_synthesized());
+        assert!(!log.points()[5].is_synthesized());
+        assert_eq!(log.source_ranges()[1].1, 6..8);
+    }
+
+    #[test]
+    fn interpolates_across_time_segments() {
+        let mut log = GPXTrackLog::new();
+        for i in [0, 1, 5, 6] {
+            log.push(point(35.0, 139.0 + i as f64 * 0.0001, i, "N"));
+        }
+        // 2秒で区間を分けていても 30 秒までの抜けは埋める
+        let gap = GPXSegmentGap::new(Some(std::time::Duration::from_secs(2)), None);
+        assert_eq!(log.segments(&gap).len(), 2);
+        assert_eq!(
+            log.interpolate_gaps(std::time::Duration::from_secs(30), &gap),
+            3
+        );
+        assert_eq!(log.segments(&gap).len(), 1);
+
+        // 距離で分けた区間の間は埋めない
+        let mut log = GPXTrackLog::new();
+        for (i, lon) in [(0, 139.0), (1, 139.0001), (5, 139.1), (6, 139.1001)] {
+            log.push(point(35.0, lon, i, "N"));
+        }
+        let gap = GPXSegmentGap::new(Some(std::time::Duration::from_secs(2)), Some(500.0));
+        assert_eq!(
+            log.interpolate_gaps(std::time::Duration::from_secs(30), &gap),
+            0
+        );
     }
 }
@@ -1,37 +1,141 @@
 use chrono::{DateTime, NaiveDateTime, TimeZone};
 use chrono_tz::{Asia::Tokyo, Tz};
 
+use super::escape_xml;
+
+// 地球の平均半径 (m)
+pub(crate) const EARTH_RADIUS: f64 = 6_371_008.8;
+
 #[derive(Debug, Clone)]
 pub(crate) struct GPXTrackPoint {
     lat: f64,
     lon: f64,
-    ele: f64,
+    ele: Option<f64>,
     speed: f64,
     time: String,
+    event: Option<String>,
+    // X, Y, Z 軸の加速度 (G)
+    acceleration: Option<[f64; 3]>,
+    voltage: Option<f64>,
+    // 補間で作った点
+    synthesized: bool,
 }
 
 impl GPXTrackPoint {
-    pub(crate) fn new(lat: f64, lon: f64, ele: f64, speed: f64, time: String) -> Self {
+    pub(crate) fn new(lat: f64, lon: f64, ele: Option<f64>, speed: f64, time: String) -> Self {
         GPXTrackPoint {
             lat,
             lon,
             ele,
             speed,
             time,
+            event: None,
+            acceleration: None,
+            voltage: None,
+            synthesized: false,
         }
     }
 
+    pub(crate) fn lat(&self) -> f64 {
+        self.lat
+    }
+
+    pub(crate) fn lon(&self) -> f64 {
+        self.lon
+    }
+
+    pub(crate) fn set_position(&mut self, lat: f64, lon: f64) {
+        self.lat = lat;
+        self.lon = lon;
+    }
+
+    pub(crate) fn ele(&self) -> Option<f64> {
+        self.ele
+    }
+
+    pub(crate) fn set_ele(&mut self, ele: Option<f64>) {
+        self.ele = ele;
+    }
+
+    // km/h
+    pub(crate) fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub(crate) fn event(&self) -> Option<&str> {
+        self.event.as_deref()
+    }
+
+    pub(crate) fn set_event(&mut self, event: Option<String>) {
+        self.event = event;
+    }
+
+    pub(crate) fn acceleration(&self) -> Option<[f64; 3]> {
+        self.acceleration
+    }
+
+    pub(crate) fn set_acceleration(&mut self, acceleration: Option<[f64; 3]>) {
+        self.acceleration = acceleration;
+    }
+
+    pub(crate) fn voltage(&self) -> Option<f64> {
+        self.voltage
+    }
+
+    pub(crate) fn set_voltage(&mut self, voltage: Option<f64>) {
+        self.voltage = voltage;
+    }
+
+    pub(crate) fn is_synthesized(&self) -> bool {
+        self.synthesized
+    }
+
+    pub(crate) fn set_synthesized(&mut self, synthesized: bool) {
+        self.synthesized = synthesized;
+    }
+
     pub(crate) fn to_gpx_string(&self) -> String {
         let timestamp = self.timestamp().unwrap_or_else(|_| {
             // If timestamp parsing fails, use a default value
-            Tokyo
-                .from_local_datetime(&NaiveDateTime::from_timestamp(0, 0))
+            DateTime::from_timestamp(0, 0)
                 .unwrap()
+                .with_timezone(&Tokyo)
         });
 
+        // 標高が不明な場合は <ele> を出力しない
+        let ele = match self.ele {
+            Some(ele) => format!("<ele>{:.2}</ele>", ele),
+            None => String::new(),
+        };
+
+        let mut extensions = String::new();
+        if let Some(event) = &self.event {
+            extensions.push_str(&format!("<zdr:event>{}</zdr:event>", escape_xml(event)));
+        }
+        if let Some([x, y, z]) = self.acceleration {
+            extensions.push_str(&format!(
+                "<zdr:x_accel>{:.2}</zdr:x_accel><zdr:y_accel>{:.2}</zdr:y_accel><zdr:z_accel>{:.2}</zdr:z_accel>",
+                x, y, z
+            ));
+        }
+        if let Some(voltage) = self.voltage {
+            extensions.push_str(&format!("<zdr:voltage>{:.1}</zdr:voltage>", voltage));
+        }
+        if self.synthesized {
+            extensions.push_str("<zdr:synthesized>true</zdr:synthesized>");
+        }
+        if !extensions.is_empty() {
+            extensions = format!("<extensions>{}</extensions>", extensions);
+        }
+
         format!(
-            "<trkpt lat=\"{:.7}\" lon=\"{:.7}\"><ele>{:.2}</ele><time>{}</time><desc>{:.2} km/h</desc></trkpt>",
-            self.lat, self.lon, self.ele, timestamp.format("%Y-%m-%dT%H:%M:%S%:z"), self.speed
+            "<trkpt lat=\"{:.7}\" lon=\"{:.7}\">{}<time>{}</time><desc>{:.2} km/h</desc>{}</trkpt>",
+            self.lat,
+            self.lon,
+            ele,
+            timestamp.format("%Y-%m-%dT%H:%M:%S%:z"),
+            self.speed,
+            extensions
         )
     }
 
@@ -39,19 +143,58 @@ impl GPXTrackPoint {
         if self.time.is_empty() {
             return Err("Time is empty".to_string());
         }
-        let timestamp = NaiveDateTime::parse_from_str(&self.time, "%Y-%m-%d %H:%M:%S")
-            .map(|naive_date| Tokyo.from_local_datetime(&naive_date).unwrap());
+        NaiveDateTime::parse_from_str(&self.time, "%Y-%m-%d %H:%M:%S")
+            .map(|naive_date| Tokyo.from_local_datetime(&naive_date).unwrap())
+            .map_err(|_| "Failed to parse timestamp".to_string())
+    }
 
-        if timestamp.is_err() {
-            Err("Failed to parse timestamp".to_string())
-        } else {
-            Ok(timestamp.unwrap())
-        }
+    // 2点間の大圏距離 (m) を haversine で求める
+    pub(crate) fn distance_to(&self, other: &GPXTrackPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+
+    // other への方位角 (真北から時計回りの度)
+    pub(crate) fn bearing_to(&self, other: &GPXTrackPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lon = (other.lon - self.lon).to_radians();
+        let y = d_lon.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+        (y.atan2(x).to_degrees() + 360.0) % 360.0
     }
 }
 
-fn to_gpx_string(child: &str) -> String {
-    format!(
-        "<gpx xmlns=\"http://www.topografix.com/GPX/1/1\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd\" version=\"1.1\" creator=\"zdr055_gpx\">\n{}\n</gpx>", child
-    )
+// origin を原点とした平面座標 (m) との相互変換。数 km 程度の範囲なら正距円筒図法で十分
+pub(crate) struct LocalProjection {
+    origin_lat: f64,
+    origin_lon: f64,
+    cos_lat: f64,
+}
+
+impl LocalProjection {
+    pub(crate) fn new(origin: &GPXTrackPoint) -> Self {
+        LocalProjection {
+            origin_lat: origin.lat,
+            origin_lon: origin.lon,
+            cos_lat: origin.lat.to_radians().cos(),
+        }
+    }
+
+    pub(crate) fn project(&self, point: &GPXTrackPoint) -> (f64, f64) {
+        (
+            (point.lon - self.origin_lon).to_radians() * self.cos_lat * EARTH_RADIUS,
+            (point.lat - self.origin_lat).to_radians() * EARTH_RADIUS,
+        )
+    }
+
+    // project の逆変換。 (緯度, 経度) を返す
+    pub(crate) fn unproject(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            self.origin_lat + (y / EARTH_RADIUS).to_degrees(),
+            self.origin_lon + (x / (EARTH_RADIUS * self.cos_lat)).to_degrees(),
+        )
+    }
 }
@@ -0,0 +1,36 @@
+// GeoJSON と統計の JSON 出力で使う、文字列を組み立てるための小さな関数群
+use crate::gpx::track_point::GPXTrackPoint;
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%:z";
+
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+pub(crate) fn json_time(point: Option<&GPXTrackPoint>) -> String {
+    point
+        .and_then(|p| p.timestamp().ok())
+        .map(|t| escape_json(&t.format(TIME_FORMAT).to_string()))
+        .unwrap_or("null".to_string())
+}
+
+pub(crate) fn json_number(value: Option<f64>) -> String {
+    value
+        .filter(|v| v.is_finite())
+        .map(|v| v.to_string())
+        .unwrap_or("null".to_string())
+}
@@ -0,0 +1,336 @@
+use chrono_tz::Tz;
+
+use crate::container::zip::ZIPArchive;
+use crate::gpx::escape_xml;
+use crate::gpx::track_log::{GPXSegmentGap, GPXTrackLog};
+use crate::gpx::track_point::GPXTrackPoint;
+
+// 速度区分ごとの上限 (km/h) と線の色。KML の色は aabbggrr
+const SPEED_STYLES: [(f64, &str); 6] = [
+    (20.0, "ffff0000"),
+    (40.0, "ffffff00"),
+    (60.0, "ff00ff00"),
+    (80.0, "ff00ffff"),
+    (100.0, "ff0080ff"),
+    (f64::INFINITY, "ff0000ff"),
+];
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%:z";
+// 通常録画中のイベント種別
+const NORMAL_EVENT: &str = "N";
+
+#[derive(Debug)]
+pub(crate) struct KML {
+    name: String,
+    children: Vec<GPXTrackLog>,
+    segment_gap: GPXSegmentGap,
+    timezone: Tz,
+}
+
+impl KML {
+    pub(crate) fn new(name: &str, children: Vec<GPXTrackLog>) -> Self {
+        KML {
+            name: name.to_string(),
+            children,
+            segment_gap: GPXSegmentGap::default(),
+            timezone: chrono_tz::Asia::Tokyo,
+        }
+    }
+
+    pub(crate) fn set_segment_gap(&mut self, segment_gap: GPXSegmentGap) {
+        self.segment_gap = segment_gap;
+    }
+
+    pub(crate) fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = timezone;
+    }
+
+    pub(crate) fn to_str(&self) -> String {
+        let styles = SPEED_STYLES
+            .iter()
+            .enumerate()
+            .map(|(i, (_, color))| {
+                format!(
+                    "<Style id=\"speed{}\"><LineStyle><color>{}</color><width>4</width></LineStyle></Style>",
+                    i, color
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("");
+
+        let mut tracks = Vec::new();
+        let mut speed_lines = Vec::new();
+        let mut events = Vec::new();
+        for child in self.children.iter() {
+            let segments = child.segments(&self.segment_gap);
+            tracks.push(track_placemark(child, &segments));
+            for segment in segments.iter() {
+                speed_lines.extend(speed_placemarks(segment));
+            }
+            events.extend(event_placemarks(child));
+        }
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n<Document><name>{}</name>{}<Style id=\"track\"><IconStyle><Icon><href>http://earth.google.com/images/kml-icons/track-directional/track-0.png</href></Icon></IconStyle><LineStyle><color>7fffffff</color><width>2</width></LineStyle></Style>\n<Folder><name>Tracks</name>{}</Folder>\n<Folder><name>Speed</name>{}</Folder>\n<Folder><name>Events</name>{}</Folder>\n</Document>\n</kml>\n",
+            escape_xml(&self.name),
+            styles,
+            tracks.join(""),
+            speed_lines.join(""),
+            events.join("")
+        )
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let kml_string = self.to_str();
+        std::fs::write(path, kml_string).map_err(|e| format!("Failed to write KML file: {}", e))
+    }
+
+    // KMZ は doc.kml を格納した ZIP ファイル
+    pub(crate) fn save_kmz(&self, path: &std::path::Path) -> Result<(), String> {
+        let mut archive = ZIPArchive::new();
+        archive.set_timezone(self.timezone);
+        archive.add_file("doc.kml", self.to_str().into_bytes());
+        std::fs::write(path, archive.to_bytes())
+            .map_err(|e| format!("Failed to write KMZ file: {}", e))
+    }
+}
+
+fn coordinates(point: &GPXTrackPoint) -> String {
+    format!(
+        "{:.7},{:.7},{:.2}",
+        point.lon(),
+        point.lat(),
+        point.ele().unwrap_or(0.0)
+    )
+}
+
+fn altitude_mode(segment: &[GPXTrackPoint]) -> &'static str {
+    if segment.iter().all(|point| point.ele().is_some()) {
+        "absolute"
+    } else {
+        "clampToGround"
+    }
+}
+
+// 時刻付きの gx:Track。区間ごとに gx:MultiTrack の子にする
+fn track_placemark(child: &GPXTrackLog, segments: &[&[GPXTrackPoint]]) -> String {
+    let tracks = segments
+        .iter()
+        .map(|segment| {
+            let points = segment
+                .iter()
+                .filter_map(|point| point.timestamp().ok().map(|time| (time, point)))
+                .collect::<Vec<_>>();
+            let whens = points
+                .iter()
+                .map(|(time, _)| format!("<when>{}</when>", time.format(TIME_FORMAT)))
+                .collect::<Vec<String>>();
+            let coords = points
+                .iter()
+                .map(|(_, point)| {
+                    format!(
+                        "<gx:coord>{:.7} {:.7} {:.2}</gx:coord>",
+                        point.lon(),
+                        point.lat(),
+                        point.ele().unwrap_or(0.0)
+                    )
+                })
+                .collect::<Vec<String>>();
+            format!(
+                "<gx:Track><altitudeMode>{}</altitudeMode>{}{}</gx:Track>",
+                altitude_mode(segment),
+                whens.join(""),
+                coords.join("")
+            )
+        })
+        .collect::<Vec<String>>();
+
+    format!(
+        "<Placemark><name>{}</name><styleUrl>#track</styleUrl><gx:MultiTrack><gx:interpolate>0</gx:interpolate>{}</gx:MultiTrack></Placemark>",
+        escape_xml(child.name().unwrap_or_default()),
+        tracks.join("")
+    )
+}
+
+fn speed_style_index(speed: f64) -> usize {
+    SPEED_STYLES
+        .iter()
+        .position(|(max_speed, _)| speed < *max_speed)
+        .unwrap_or(SPEED_STYLES.len() - 1)
+}
+
+fn speed_style_name(index: usize) -> String {
+    let min_speed = if index == 0 {
+        0.0
+    } else {
+        SPEED_STYLES[index - 1].0
+    };
+    let max_speed = SPEED_STYLES[index].0;
+    if max_speed.is_finite() {
+        format!("{:.0}-{:.0} km/h", min_speed, max_speed)
+    } else {
+        format!("{:.0}+ km/h", min_speed)
+    }
+}
+
+// 同じ速度区分が続く範囲ごとに色分けした LineString を作る
+fn speed_placemarks(segment: &[GPXTrackPoint]) -> Vec<String> {
+    let mut placemarks = Vec::new();
+    let mut start = 0;
+    while start + 1 < segment.len() {
+        let style = speed_style_index(segment[start + 1].speed());
+        let mut end = start + 1;
+        while end + 1 < segment.len() && speed_style_index(segment[end + 1].speed()) == style {
+            end += 1;
+        }
+
+        // 隣の区間と途切れないよう、直前の点から線を引く
+        let run = &segment[start..=end];
+        let coords = run.iter().map(coordinates).collect::<Vec<String>>();
+        let time_span = match (run[0].timestamp(), run[run.len() - 1].timestamp()) {
+            (Ok(begin), Ok(end)) => format!(
+                "<TimeSpan><begin>{}</begin><end>{}</end></TimeSpan>",
+                begin.format(TIME_FORMAT),
+                end.format(TIME_FORMAT)
+            ),
+            _ => String::new(),
+        };
+        placemarks.push(format!(
+            "<Placemark><name>{}</name>{}<styleUrl>#speed{}</styleUrl><LineString><tessellate>1</tessellate><altitudeMode>{}</altitudeMode><coordinates>{}</coordinates></LineString></Placemark>",
+            speed_style_name(style),
+            time_span,
+            style,
+            altitude_mode(run),
+            coords.join(" ")
+        ));
+        start = end;
+    }
+    placemarks
+}
+
+// 通常以外のイベントが始まった点に目印を置く。通常に戻った点には置かない
+fn event_placemarks(child: &GPXTrackLog) -> Vec<String> {
+    let mut placemarks = Vec::new();
+    let mut last_event: Option<&str> = None;
+    for point in child.points().iter() {
+        let event = point.event();
+        if event.is_some_and(|event| event != NORMAL_EVENT) && event != last_event {
+            let time_stamp = match point.timestamp() {
+                Ok(time) => format!(
+                    "<TimeStamp><when>{}</when></TimeStamp>",
+                    time.format(TIME_FORMAT)
+                ),
+                Err(_) => String::new(),
+            };
+            placemarks.push(format!(
+                "<Placemark><name>Event: {}</name>{}<description>{:.2} km/h</description><Point><coordinates>{}</coordinates></Point></Placemark>",
+                escape_xml(event.unwrap_or_default()),
+                time_stamp,
+                point.speed(),
+                coordinates(point)
+            ));
+        }
+        last_event = event;
+    }
+    placemarks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpx::reader::{XMLReader, XMLToken};
+
+    // (要素, テキスト) の一覧
+    fn texts(s: &str) -> Vec<(String, String)> {
+        let mut reader = XMLReader::new(s);
+        let mut path: Vec<String> = Vec::new();
+        let mut texts = Vec::new();
+        while let Some(token) = reader.next_token().unwrap() {
+            match token {
+                XMLToken::Start {
+                    name, self_closing, ..
+                } => {
+                    if !self_closing {
+                        path.push(name);
+                    }
+                }
+                XMLToken::End { .. } => {
+                    path.pop();
+                }
+                XMLToken::Text(text) => texts.push((path[path.len() - 1].clone(), text)),
+            }
+        }
+        texts
+    }
+
+    fn values<'a>(texts: &'a [(String, String)], name: &str) -> Vec<&'a str> {
+        texts
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, text)| text.as_str())
+            .collect()
+    }
+
+    fn sample_kml() -> KML {
+        let mut log = GPXTrackLog::new();
+        log.set_name("trip");
+        // イベント録画のクリップは先頭からイベント中になっている
+        let events = ["E", "E", "N", "N", "G", "G", "N"];
+        for (i, event) in events.iter().enumerate() {
+            // 4点目の前で録画が途切れている
+            let second = if i < 3 { i } else { i + 600 };
+            let mut point = GPXTrackPoint::new(
+                35.0 + i as f64 * 0.0001,
+                139.0,
+                if i < 3 { Some(10.0) } else { None },
+                if i < 3 { 10.0 } else { 50.0 },
+                format!("2025-07-20 12:{:02}:{:02}", second / 60, second % 60),
+            );
+            point.set_event(Some(event.to_string()));
+            log.push(point);
+        }
+        let mut kml = KML::new("trip & more", vec![log]);
+        kml.set_segment_gap(GPXSegmentGap::new(
+            Some(std::time::Duration::from_secs(120)),
+            None,
+        ));
+        kml
+    }
+
+    #[test]
+    fn marks_event_starts() {
+        let texts = texts(&sample_kml().to_str());
+        let names = values(&texts, "name");
+        let events = names
+            .iter()
+            .filter(|name| name.starts_with("Event: "))
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec![&"Event: E", &"Event: G"]);
+        let whens = values(&texts, "when");
+        assert!(whens.contains(&"2025-07-20T12:00:00+09:00"));
+        assert!(whens.contains(&"2025-07-20T12:10:04+09:00"));
+    }
+
+    #[test]
+    fn writes_track_per_segment() {
+        let s = sample_kml().to_str();
+        let texts = texts(&s);
+        assert_eq!(values(&texts, "name")[0], "trip & more");
+        assert_eq!(s.matches("<gx:Track>").count(), 2);
+        assert_eq!(
+            values(&texts, "altitudeMode")[..2],
+            ["absolute", "clampToGround"]
+        );
+        let coords = values(&texts, "coord");
+        assert_eq!(coords.len(), 7);
+        assert_eq!(coords[0], "139.0000000 35.0000000 10.00");
+        assert_eq!(coords[6], "139.0000000 35.0006000 0.00");
+
+        // 速度区分の線は区間ごとに作り、区間の間はつながない
+        let speeds = values(&texts, "name")
+            .into_iter()
+            .filter(|name| name.ends_with("km/h"))
+            .collect::<Vec<_>>();
+        assert_eq!(speeds, vec!["0-20 km/h", "40-60 km/h"]);
+    }
+}
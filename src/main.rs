@@ -1,164 +1,836 @@
+// 型名は GPX, AVI などの略語をそのまま大文字で使う
+#![allow(clippy::upper_case_acronyms)]
+
 mod cli;
 mod container;
+mod csv;
+mod dem;
+mod fit;
+mod geojson;
+mod geopackage;
 mod gpx;
+mod json;
+mod kml;
+mod nmea;
+mod polyline;
+mod replay;
+mod split;
+mod stats;
+mod subtitle;
+mod tcx;
+mod trip;
 mod zdr055;
+mod ztrk;
 
+use std::collections::HashSet;
 use std::fs::{self};
 use std::str::FromStr;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::AcqRel;
 use std::{path, thread};
 
-use cli::Cli;
+use cli::{Cli, Command, OutputFormat, ReplayArgs};
+use csv::overlay::OverlayCSV;
+use csv::CSV;
+use dem::DEM;
+use fit::FIT;
+use geojson::GeoJSON;
+use geopackage::GeoPackage;
+use gpx::metadata::GPXMetadata;
+use gpx::outlier::OutlierFilter;
 use gpx::track_log::GPXTrackLog;
+use gpx::track_point::GPXTrackPoint;
 use gpx::GPX;
+use kml::KML;
+use nmea::NMEA;
+use polyline::Polyline;
+use replay::ReplaySink;
+use split::SplitOutput;
+use stats::Statistics;
+use subtitle::Subtitle;
+use tcx::TCX;
+use zdr055::filename::{ClipFilter, ClipName};
+use zdr055::pair::{compare_telemetry, find_camera_pairs};
 use zdr055::{ZDR055MediaData, ZDR055PositionData};
+use ztrk::ZTRK;
+
+// 1つの入力ファイルから取り出したデータ
+#[derive(Clone)]
+struct Clip {
+    path: path::PathBuf,
+    log: GPXTrackLog,
+    // 解析できた生のログデータと、ストリーム内でのインデックス
+    samples: Vec<(usize, ZDR055PositionData)>,
+}
 
 fn main() {
     let args = Cli::parse();
+    if let Some(Command::Replay(replay_args)) = args.get_command() {
+        if let Err(e) = replay(replay_args) {
+            eprintln!("Error replaying: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
     let output_dir = args.get_output_path();
     let parallel = args.get_parallel_count();
     let merge_enable = args.get_merge_enabled();
     let merge_threshold = args.get_merge_threshold();
+    let trip_detector = args.get_trip_detector();
+    let mut dem = args.get_dem_path().map(|dir| DEM::new(dir));
+    let mut gpkg = match args.get_gpkg_path().map(|path| GeoPackage::create(path)) {
+        Some(Ok(mut gpkg)) => {
+            gpkg.set_segment_gap(args.get_segment_gap());
+            Some(gpkg)
+        }
+        Some(Err(e)) => {
+            eprintln!("Error creating GeoPackage: {}", e);
+            return;
+        }
+        None => None,
+    };
+    let mut split = args
+        .get_split_period()
+        .map(|period| SplitOutput::new(period, args.get_timezone()));
+    let mut stats = if args.is_stats_enabled() {
+        let mut stats = Statistics::new();
+        stats.set_segment_gap(args.get_segment_gap());
+        Some(stats)
+    } else {
+        None
+    };
 
     let input_path = args.get_input_path();
     if input_path.is_dir() {
         println!("Processing directory: {}", input_path.display());
-        let logs = process_directory(input_path, &output_dir, parallel);
-        if logs.is_err() {
-            eprintln!("Error processing directory: {}", logs.unwrap_err());
-            return;
+        let mut clip_filter = args.get_clip_filter();
+        let mut camera_pairs = Vec::new();
+        if args.is_camera_pair_dedup_enabled() {
+            let mut files = Vec::new();
+            if let Err(e) = collect_media_files(input_path, &mut files) {
+                eprintln!("Error processing directory: {}", e);
+                return;
+            }
+            files.retain(|file| clip_filter.matches(file));
+            camera_pairs = find_camera_pairs(&files);
+            if !camera_pairs.is_empty() {
+                println!(
+                    "Found {} front/rear clip pairs, reading the front camera only",
+                    camera_pairs.len()
+                );
+                clip_filter.exclude(camera_pairs.iter().map(|(_, rear)| rear.clone()));
+            }
+        }
+        let mut clips = match process_directory(
+            input_path,
+            &output_dir,
+            parallel,
+            &clip_filter,
+            args.is_track_input_enabled(),
+        ) {
+            Ok(clips) => clips,
+            Err(e) => {
+                eprintln!("Error processing directory: {}", e);
+                return;
+            }
+        };
+        read_rears_of_failed_fronts(&mut clips, &camera_pairs);
+        if args.is_camera_pair_verify_enabled() {
+            verify_camera_pairs(&clips, &camera_pairs);
+        }
+        if let Some(dem) = dem.as_mut() {
+            for clip in clips.iter_mut() {
+                apply_elevation(&mut clip.log, dem);
+            }
+        }
+        let rear_clips = paired_rear_clips(&clips, &camera_pairs);
+        for clip in clips.iter().chain(rear_clips.iter()) {
+            save_subtitles(clip, &args);
+            save_overlay_csv(clip, &output_dir, &args);
         }
-        let mut logs = logs.unwrap();
 
         if merge_enable {
             println!("--- Start merging logs ---");
-            logs.sort_by(|a, b| a.0.cmp(&b.0));
-            let mut current_log = GPXTrackLog::new();
+            sort_clips(&mut clips);
+            let mut current_clips: Vec<Clip> = Vec::new();
             let mut output_path = path::PathBuf::new();
-            for (path, log) in &logs {
+            // 前のクリップとの間に駐車監視のクリップがあったか
+            let mut parked = false;
+            for clip in &clips {
+                let (path, log) = (&clip.path, &clip.log);
+                if let Some(detector) = &trip_detector {
+                    if detector.is_parking_clip(path) {
+                        println!("Parking clip: {}", path.display());
+                        save_logs(
+                            vec![clip.clone()],
+                            &get_output_path(path, &output_dir),
+                            &args,
+                            &mut gpkg,
+                            &mut stats,
+                            &mut split,
+                        );
+                        parked = true;
+                        continue;
+                    }
+                }
                 if output_path.as_os_str().is_empty() {
-                    output_path = get_output_path(&path, &output_dir);
+                    output_path = get_trip_output_path(path, &output_dir);
                     println!("Output changed: {}", output_path.display());
                 }
                 println!("Merging: {} -> {}", path.display(), output_path.display());
-                if current_log.is_empty() {
-                    current_log.extend(log.clone());
-                } else if let (Some(last_point), Some(first_point)) =
-                    (current_log.last(), log.first())
-                {
-                    // Check if the time difference is within the merge threshold
-                    let first_timestamp = first_point.timestamp();
-                    if first_timestamp.is_err() {
-                        eprintln!(
-                            "First point in log {} has no timestamp: {}",
-                            path.display(),
-                            first_timestamp.unwrap_err()
-                        );
-                        let gpx = GPX::new(log.clone());
-                        if gpx.save(&get_output_path(path, &output_dir)).is_err() {
-                            eprintln!("Error saving file: {}", &path.display());
-                        } else {
-                            println!("Saved GPX file: {}", path.display());
+                let last_point = current_clips.iter().rev().find_map(|c| c.log.last());
+                let same_trip = match (last_point, log.first()) {
+                    (Some(last_point), Some(first_point)) => {
+                        match (last_point.timestamp(), first_point.timestamp()) {
+                            (Ok(last_timestamp), Ok(first_timestamp)) => {
+                                // 時間が重なっている場合は差を 0 とみなす (重複分は後で除く)
+                                let time_diff = (first_timestamp - last_timestamp)
+                                    .to_std()
+                                    .unwrap_or_default();
+                                let jump = trip::implausible_jump(
+                                    last_point,
+                                    first_point,
+                                    time_diff,
+                                    args.get_merge_distance(),
+                                    args.get_merge_max_speed(),
+                                );
+                                if let Some(reason) = jump {
+                                    println!("New trip: {}: {}", path.display(), reason);
+                                    false
+                                } else {
+                                    match (
+                                        &trip_detector,
+                                        current_clips.iter().rev().find(|c| !c.log.is_empty()),
+                                    ) {
+                                        // --merge-threshold は activity モードでも上限として効かせる
+                                        (Some(_), _) if time_diff > *merge_threshold => {
+                                            println!(
+                                                "New trip: {}: gap {} exceeds --merge-threshold",
+                                                path.display(),
+                                                humantime::format_duration(time_diff)
+                                            );
+                                            false
+                                        }
+                                        (Some(detector), Some(previous)) => {
+                                            match detector.split_reason(
+                                                &previous.path,
+                                                &previous.log,
+                                                path,
+                                                log,
+                                                parked,
+                                            ) {
+                                                Some(reason) => {
+                                                    println!(
+                                                        "New trip: {}: {}",
+                                                        path.display(),
+                                                        reason
+                                                    );
+                                                    false
+                                                }
+                                                None => true,
+                                            }
+                                        }
+                                        _ => time_diff <= *merge_threshold,
+                                    }
+                                }
+                            }
+                            // 時刻で比べられないものは別のトリップにする
+                            (last_timestamp, first_timestamp) => {
+                                eprintln!(
+                                    "Cannot compare timestamps of {} and {}: {}",
+                                    path.display(),
+                                    output_path.display(),
+                                    last_timestamp
+                                        .err()
+                                        .or(first_timestamp.err())
+                                        .unwrap_or_default()
+                                );
+                                false
+                            }
                         }
-                        current_log = GPXTrackLog::new();
-                        continue;
                     }
-                    let first_timestamp = first_timestamp.unwrap();
-                    let last_timestamp = last_point.timestamp();
-                    if last_timestamp.is_err() {
-                        eprintln!("Last point in current log has no timestamp");
-                        let gpx = GPX::new(log.clone());
-                        if gpx.save(&get_output_path(path, &output_dir)).is_err() {
-                            eprintln!("Error saving file: {}", &path.display());
-                        } else {
-                            println!("Saved GPX file: {}", path.display());
-                        }
-                        current_log = GPXTrackLog::new();
-                        continue;
-                    }
-                    let last_timestamp = last_timestamp.unwrap();
-
-                    let time_diff = (first_timestamp - last_timestamp).to_std();
-                    if time_diff.is_err() {
-                        eprintln!(
-                            "Error calculating time difference for logs: {} and {}, {}",
-                            path.display(),
-                            output_path.display(),
-                            time_diff.unwrap_err()
-                        );
-                        let gpx = GPX::new(log.clone());
-                        if gpx.save(&get_output_path(path, &output_dir)).is_err() {
-                            eprintln!("Error saving file: {}", &path.display());
-                        } else {
-                            println!("Saved GPX file: {}", path.display());
-                        }
-                        current_log = GPXTrackLog::new();
-                        continue;
-                    }
-                    let time_diff = time_diff.unwrap();
+                    // 点の無いクリップはそのままつなげる
+                    _ => true,
+                };
+                parked = false;
 
-                    if time_diff <= *merge_threshold {
-                        current_log.extend(log.clone());
-                    } else {
-                        // Save the current log and start a new one
-                        let gpx = GPX::new(current_log.clone());
-                        if gpx.save(&output_path).is_err() {
-                            eprintln!("Error saving file: {}", &output_path.display());
-                        } else {
-                            println!("Saved GPX file: {}", &output_path.display());
+                if same_trip {
+                    let clip = match last_point {
+                        Some(last_point) => {
+                            let (clip, removed) = trim_overlap(clip, last_point);
+                            if removed > 0 {
+                                println!(
+                                    "Skipped {} overlapping points: {}",
+                                    removed,
+                                    clip.path.display()
+                                );
+                            }
+                            clip
                         }
-                        current_log = log.clone();
-                        output_path = get_output_path(&path, &output_dir);
-                        println!("Output changed: {}", output_path.display());
-                    }
+                        None => clip.clone(),
+                    };
+                    current_clips.push(clip);
+                } else {
+                    // Save the current log and start a new one
+                    save_logs(
+                        current_clips,
+                        &output_path,
+                        &args,
+                        &mut gpkg,
+                        &mut stats,
+                        &mut split,
+                    );
+                    current_clips = vec![clip.clone()];
+                    output_path = get_trip_output_path(path, &output_dir);
+                    println!("Output changed: {}", output_path.display());
                 }
             }
 
-            let gpx = GPX::new(current_log.clone());
-            if gpx.save(&output_path).is_err() {
-                eprintln!("Error saving file: {}", &output_path.display());
-            } else {
-                println!("Saved GPX file: {}", output_path.display());
-            }
+            save_logs(
+                current_clips,
+                &output_path,
+                &args,
+                &mut gpkg,
+                &mut stats,
+                &mut split,
+            );
         } else {
-            for (path, log) in logs {
-                let output_path = get_output_path(&path, &output_dir);
-                let gpx = GPX::new(log);
-                if gpx.save(&output_path).is_err() {
-                    eprintln!("Error saving file: {}", &output_path.display());
-                } else {
-                    println!("Saved GPX file: {}", output_path.display());
-                }
+            for clip in clips {
+                let output_path = get_output_path(&clip.path, &output_dir);
+                save_logs(
+                    vec![clip],
+                    &output_path,
+                    &args,
+                    &mut gpkg,
+                    &mut stats,
+                    &mut split,
+                );
             }
         }
     } else {
         let output_path = get_output_path(input_path, &output_dir);
         println!("Processing file: {}", input_path.display());
-        let logs = process_media_file(input_path)
+        let mut clip = process_media_file(input_path)
             .map_err(|e| {
                 eprintln!("Error processing file: {}", e);
                 e
             })
             .unwrap();
+        if let Some(dem) = dem.as_mut() {
+            apply_elevation(&mut clip.log, dem);
+        }
+        save_subtitles(&clip, &args);
+        save_overlay_csv(&clip, &output_dir, &args);
+
+        save_logs(
+            vec![clip],
+            &output_path,
+            &args,
+            &mut gpkg,
+            &mut stats,
+            &mut split,
+        );
+    }
+
+    if let Some(split) = split {
+        for (key, tracks, samples) in split.into_periods() {
+            let output_path = output_dir.join(format!("{}.gpx", key));
+            write_outputs(&key, tracks, samples, &output_path, &args);
+        }
+    }
+
+    if let Some(stats) = stats {
+        if args.is_stats_table_enabled() {
+            println!("{}", stats.to_table());
+        }
+        if let Some(path) = args.get_stats_json_path() {
+            match stats.save_json(path) {
+                Ok(_) => println!("Saved statistics file: {}", path.display()),
+                Err(e) => eprintln!("Error saving file: {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    if let (Some(gpkg), Some(path)) = (gpkg, args.get_gpkg_path()) {
+        match gpkg.finish() {
+            Ok(_) => println!("Saved GPKG file: {}", path.display()),
+            Err(e) => eprintln!("Error saving file: {}: {}", path.display(), e),
+        }
+    }
+}
+
+// last_point の時刻以前の点とサンプルを除いたクリップを返す。
+// EVENT フォルダへのコピーなど、同じ時間を重ねて記録したクリップの重複を除くため。
+// 除いた点の数も返す
+fn trim_overlap(clip: &Clip, last_point: &GPXTrackPoint) -> (Clip, usize) {
+    let mut clip = clip.clone();
+    let Ok(last_timestamp) = last_point.timestamp() else {
+        return (clip, 0);
+    };
+    let removed = clip.log.remove_until(&last_timestamp);
+    clip.samples.retain(|(_, sample)| {
+        sample
+            .to_gpx_point()
+            .timestamp()
+            .map_or(true, |timestamp| timestamp > last_timestamp)
+    });
+    (clip, removed)
+}
+
+// track_per_clip が無効な場合はクリップ単位のログを1つの <trk> にまとめて保存する
+fn save_logs(
+    clips: Vec<Clip>,
+    output_path: &path::Path,
+    args: &Cli,
+    gpkg: &mut Option<GeoPackage>,
+    stats: &mut Option<Statistics>,
+    split: &mut Option<SplitOutput>,
+) {
+    let name = output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    // 入力の GPX などを同じ名前の出力で上書きしてしまう場合は書き出さない
+    if split.is_none() {
+        let overwritten = args.get_output_formats().iter().find_map(|format| {
+            let path = output_path.with_extension(format.extension());
+            clips
+                .iter()
+                .find(|clip| is_same_file(&clip.path, &path))
+                .map(|clip| clip.path.clone())
+        });
+        if let Some(input) = overwritten {
+            eprintln!(
+                "Refusing to overwrite input file {}; choose another output directory with -o",
+                input.display()
+            );
+            return;
+        }
+    }
+    if let Some(gpkg) = gpkg.as_mut() {
+        let trip = clips
+            .iter()
+            .map(|clip| (&clip.log, clip.samples.as_slice()))
+            .collect::<Vec<_>>();
+        if let Err(e) = gpkg.add_trip(name, &trip) {
+            eprintln!("Error adding trip to GeoPackage: {}: {}", name, e);
+        }
+    }
+
+    let mut samples = Vec::new();
+    let mut logs = Vec::new();
+    for clip in clips {
+        let source = clip
+            .path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string();
+        samples.extend(
+            clip.samples
+                .into_iter()
+                .map(|(index, sample)| (source.clone(), index, sample)),
+        );
+        logs.push(clip.log);
+    }
+
+    let mut tracks: Vec<GPXTrackLog> = if args.is_track_per_clip() {
+        logs.into_iter().filter(|log| !log.is_empty()).collect()
+    } else {
+        let mut merged_log = GPXTrackLog::new();
+        for (i, log) in logs.into_iter().enumerate() {
+            if i == 0 {
+                merged_log = log;
+            } else {
+                merged_log.extend(log);
+            }
+        }
+        vec![merged_log]
+    };
+
+    if let Some(noise) = args.get_smooth_noise() {
+        for track in tracks.iter_mut() {
+            track.smooth(noise, &args.get_segment_gap());
+        }
+    }
+    if let Some(max_gap) = args.get_interpolate_gap() {
+        let added = tracks
+            .iter_mut()
+            .map(|track| track.interpolate_gaps(max_gap, &args.get_segment_gap()))
+            .sum::<usize>();
+        println!("Interpolated {}: added {} points", name, added);
+    }
+
+    if let Some(stats) = stats.as_mut() {
+        stats.add_trip(name, &tracks);
+    }
+
+    if let Some(algorithm) = args.get_simplify_algorithm() {
+        let total = tracks
+            .iter()
+            .map(|track| track.points().len())
+            .sum::<usize>();
+        let removed = tracks
+            .iter_mut()
+            .map(|track| {
+                track.simplify(
+                    algorithm,
+                    args.get_simplify_tolerance(),
+                    &args.get_segment_gap(),
+                )
+            })
+            .sum::<usize>();
+        println!(
+            "Simplified {}: removed {} of {} points ({:.1}%)",
+            name,
+            removed,
+            total,
+            if total > 0 {
+                removed as f64 * 100.0 / total as f64
+            } else {
+                0.0
+            }
+        );
+    }
+
+    if let Some(split) = split.as_mut() {
+        split.add(tracks, samples);
+        return;
+    }
+    write_outputs(name, tracks, samples, output_path, args);
+}
+
+// tracks と samples を指定された各形式で output_path (拡張子は形式ごとに変える) に保存する
+fn write_outputs(
+    name: &str,
+    tracks: Vec<GPXTrackLog>,
+    samples: Vec<(String, usize, ZDR055PositionData)>,
+    output_path: &path::Path,
+    args: &Cli,
+) {
+    let sources = tracks
+        .iter()
+        .flat_map(|log| log.sources())
+        .collect::<Vec<&String>>();
+    let mut metadata = GPXMetadata::new(
+        name,
+        &format!("Generated by zdr055_gpx from {} clip(s)", sources.len()),
+    );
+    metadata.set_time(chrono::Utc::now().with_timezone(&args.get_timezone()));
+    if let Some(device) = tracks.iter().find_map(|log| log.device()) {
+        metadata.set_device(device.clone());
+    }
+    for source in sources {
+        metadata.add_source(source);
+    }
+
+    for format in args.get_output_formats() {
+        let output_path = output_path.with_extension(format.extension());
+        let result = match format {
+            OutputFormat::Gpx => {
+                let mut gpx = GPX::new(tracks.clone());
+                gpx.set_metadata(metadata.clone());
+                gpx.set_segment_gap(args.get_segment_gap());
+                gpx.save(&output_path)
+            }
+            OutputFormat::Kml | OutputFormat::Kmz => {
+                let mut kml = KML::new(name, tracks.clone());
+                kml.set_segment_gap(args.get_segment_gap());
+                kml.set_timezone(args.get_timezone());
+                if *format == OutputFormat::Kmz {
+                    kml.save_kmz(&output_path)
+                } else {
+                    kml.save(&output_path)
+                }
+            }
+            OutputFormat::Geojson => {
+                let mut geojson = GeoJSON::new(tracks.clone());
+                geojson.set_segment_gap(args.get_segment_gap());
+                geojson.set_with_points(args.is_geojson_points_enabled());
+                geojson.save(&output_path)
+            }
+            OutputFormat::Csv => {
+                let mut csv = CSV::new(samples.clone());
+                csv.set_delimiter(args.get_csv_delimiter());
+                csv.set_header_style(args.get_csv_header_style());
+                csv.set_encoding(args.get_csv_encoding());
+                csv.save(&output_path)
+            }
+            OutputFormat::Fit => {
+                let mut fit = FIT::new(tracks.clone());
+                fit.set_segment_gap(args.get_segment_gap());
+                fit.set_timezone(args.get_timezone());
+                fit.save(&output_path)
+            }
+            OutputFormat::Tcx => TCX::new(tracks.clone()).save(&output_path),
+            OutputFormat::Polyline => {
+                let mut polyline = Polyline::new(tracks.clone());
+                polyline.set_segment_gap(args.get_segment_gap());
+                polyline.set_precision(args.get_polyline_precision());
+                polyline.save(&output_path)
+            }
+            OutputFormat::Ztrk => {
+                let mut ztrk = ZTRK::new(tracks.clone());
+                ztrk.set_precision(args.get_ztrk_precision());
+                ztrk.save(&output_path)
+            }
+            OutputFormat::Nmea => NMEA::new(
+                samples
+                    .iter()
+                    .map(|(_, _, sample)| sample.clone())
+                    .collect(),
+            )
+            .save(&output_path),
+        };
+        match result {
+            Ok(_) => println!(
+                "Saved {} file: {}",
+                format.extension().to_uppercase(),
+                output_path.display()
+            ),
+            Err(e) => eprintln!("Error saving file: {}: {}", output_path.display(), e),
+        }
+    }
+}
+
+// 標準出力を NMEA の送り先にできるよう、進捗は表示せずにファイルを読む
+fn replay(args: &ReplayArgs) -> Result<(), String> {
+    let input_path = args.get_input_path();
+    let mut files = Vec::new();
+    let mut camera_pairs = Vec::new();
+    if input_path.is_dir() {
+        collect_media_files(input_path, &mut files)?;
+        // 前後のカメラの組は同じ走行なので前のカメラのクリップだけを流す
+        camera_pairs = find_camera_pairs(&files);
+        let rears = camera_pairs
+            .iter()
+            .map(|(_, rear)| rear)
+            .collect::<HashSet<_>>();
+        files.retain(|file| !rears.contains(file));
+    } else {
+        files.push(input_path.clone());
+    }
+
+    let mut clips = Vec::new();
+    for file in files {
+        match process_media_file(&file) {
+            Ok(clip) => clips.push(clip),
+            Err(e) => eprintln!("Error processing file {}: {}", file.display(), e),
+        }
+    }
+    read_rears_of_failed_fronts(&mut clips, &camera_pairs);
+    sort_clips(&mut clips);
+    // マージと同じく、重なって記録された時間は先のクリップの分だけを流す。
+    // 標準出力には NMEA を流すので、メッセージは標準エラー出力へ
+    let mut last_point: Option<GPXTrackPoint> = None;
+    let mut samples = Vec::new();
+    for clip in clips {
+        let clip = match &last_point {
+            Some(last_point) => {
+                let (clip, removed) = trim_overlap(&clip, last_point);
+                if removed > 0 {
+                    eprintln!(
+                        "Skipped {} overlapping points: {}",
+                        removed,
+                        clip.path.display()
+                    );
+                }
+                clip
+            }
+            None => clip,
+        };
+        if let Some(point) = clip.log.last() {
+            last_point = Some(point.clone());
+        }
+        samples.extend(clip.samples.into_iter().map(|(_, sample)| sample));
+    }
+
+    let sink = match (args.get_tcp_address(), args.get_udp_address()) {
+        (Some(address), _) => ReplaySink::tcp(address)?,
+        (None, Some(address)) => ReplaySink::udp(address)?,
+        (None, None) => ReplaySink::Stdout,
+    };
+    let result = replay::replay(
+        &NMEA::new(samples).epochs(),
+        &sink,
+        args.get_speed(),
+        args.get_segment_gap(),
+    );
+    sink.close();
+    result
+}
+
+// ファイル名ではなく最初の点の時刻の順に並べる。時刻の分からないものはファイル名の日時の順で最後
+fn sort_clips(clips: &mut [Clip]) {
+    clips.sort_by_cached_key(|clip| {
+        let timestamp = clip.log.first().and_then(|point| point.timestamp().ok());
+        let name = ClipName::from_path(&clip.path).map(|name| name.start());
+        (timestamp.is_none(), timestamp, name, clip.path.clone())
+    });
+}
+
+fn collect_media_files(
+    dir_path: &path::Path,
+    files: &mut Vec<path::PathBuf>,
+) -> Result<(), String> {
+    let entries = fs::read_dir(dir_path)
+        .map_err(|e| format!("Error reading directory: {}: {}", dir_path.display(), e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_media_files(&path, files)?;
+        } else if path
+            .extension()
+            .and_then(|s| s.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("avi"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
 
-        let gpx = GPX::new(logs);
-        if gpx.save(&output_path).is_err() {
-            eprintln!("Error saving file: {}", &output_path.display());
+// 字幕は動画プレイヤーが自動で読み込めるよう、出力先ではなく AVI ファイルの隣に保存する
+fn save_subtitles(clip: &Clip, args: &Cli) {
+    if clip.samples.is_empty() {
+        return;
+    }
+    let mut subtitle = Subtitle::new(
+        clip.samples
+            .iter()
+            .map(|(_, sample)| sample.clone())
+            .collect(),
+    );
+    subtitle.set_template(args.get_subtitle_template());
+    // ファイル名の日時が録画の開始時刻なので、最初のサンプルが遅れていてもキューを動画に合わせられる
+    if let Some(name) = ClipName::from_path(&clip.path) {
+        subtitle.set_start(name.start());
+    }
+    for format in args.get_subtitle_formats() {
+        let output_path = clip.path.with_extension(format.extension());
+        match subtitle.save(&output_path, *format) {
+            Ok(_) => println!(
+                "Saved {} file: {}",
+                format.extension().to_uppercase(),
+                output_path.display()
+            ),
+            Err(e) => eprintln!("Error saving file: {}: {}", output_path.display(), e),
+        }
+    }
+}
+
+// 動画編集ソフトでクリップに重ねる用なので、マージ時もクリップごとに書き出す
+fn save_overlay_csv(clip: &Clip, output_dir: &path::Path, args: &Cli) {
+    if clip.log.is_empty() {
+        return;
+    }
+    let stem = clip
+        .path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let mut overlay = OverlayCSV::new(clip.log.points().to_vec());
+    overlay.set_rate(args.get_overlay_rate());
+    if let Some(name) = ClipName::from_path(&clip.path) {
+        overlay.set_start(name.start());
+    }
+    for preset in args.get_overlay_presets() {
+        let output_path = output_dir.join(format!("{}_{}.csv", stem, preset.suffix()));
+        match overlay.save(&output_path, *preset) {
+            Ok(_) => println!("Saved CSV file: {}", output_path.display()),
+            Err(e) => eprintln!("Error saving file: {}: {}", output_path.display(), e),
         }
     }
 }
 
-fn get_output_path(in_file: &path::PathBuf, out_dir: &path::PathBuf) -> path::PathBuf {
+fn apply_elevation(log: &mut GPXTrackLog, dem: &mut DEM) {
+    for point in log.points_mut() {
+        if let Some(ele) = dem.elevation(point.lat(), point.lon()) {
+            point.set_ele(Some(ele));
+        }
+    }
+}
+
+fn get_output_path(in_file: &path::Path, out_dir: &path::Path) -> path::PathBuf {
     let filename = in_file.file_stem().unwrap().to_str().unwrap();
     out_dir.join(format!("{}.gpx", filename))
 }
 
+// マージしたトリップの出力先。ファイル名を読めれば開始日時とカメラだけを名前にする
+fn get_trip_output_path(in_file: &path::Path, out_dir: &path::Path) -> path::PathBuf {
+    match ClipName::from_path(in_file) {
+        Some(name) => out_dir.join(format!("{}.gpx", name.trip_stem())),
+        None => get_output_path(in_file, out_dir),
+    }
+}
+
+// 字幕やオーバーレイは動画ごとに要るので、読まなかった後ろのカメラのクリップの分は
+// 組になる前のカメラのクリップのデータを後ろのクリップの名前で使う
+fn paired_rear_clips(clips: &[Clip], pairs: &[(path::PathBuf, path::PathBuf)]) -> Vec<Clip> {
+    pairs
+        .iter()
+        .filter(|(_, rear)| !clips.iter().any(|clip| clip.path == *rear))
+        .filter_map(|(front, rear)| {
+            let front_clip = clips.iter().find(|clip| clip.path == *front)?;
+            Some(Clip {
+                path: rear.clone(),
+                ..front_clip.clone()
+            })
+        })
+        .collect()
+}
+
+// 前のカメラのクリップが読めなかった組は、除いておいた後ろのカメラのクリップを代わりに読む
+fn read_rears_of_failed_fronts(clips: &mut Vec<Clip>, pairs: &[(path::PathBuf, path::PathBuf)]) {
+    for (front, rear) in pairs {
+        if clips.iter().any(|clip| clip.path == *front) {
+            continue;
+        }
+        eprintln!(
+            "Front clip unavailable, reading the rear camera instead: {}",
+            rear.display()
+        );
+        match process_media_file(rear) {
+            Ok(clip) => clips.push(clip),
+            Err(e) => eprintln!("Error processing file {}: {}", rear.display(), e),
+        }
+    }
+}
+
+// 前のカメラのクリップと、読まなかった後ろのカメラのクリップのログが同じか確かめる
+fn verify_camera_pairs(clips: &[Clip], pairs: &[(path::PathBuf, path::PathBuf)]) {
+    for (front, rear) in pairs {
+        let Some(front_clip) = clips.iter().find(|clip| clip.path == *front) else {
+            continue;
+        };
+        let rear_clip = match process_media_file(rear) {
+            Ok(clip) => clip,
+            Err(e) => {
+                eprintln!("Error processing file {}: {}", rear.display(), e);
+                continue;
+            }
+        };
+        let differences = compare_telemetry(&front_clip.samples, &rear_clip.samples);
+        if differences.is_empty() {
+            println!("Pair verified: {} = {}", front.display(), rear.display());
+        } else {
+            eprintln!(
+                "Pair differs: {} / {}: {}",
+                front.display(),
+                rear.display(),
+                differences.join(", ")
+            );
+        }
+    }
+}
+
 fn process_directory(
-    dir_path: &path::PathBuf,
-    output_dir: &path::PathBuf,
+    dir_path: &path::Path,
+    output_dir: &path::Path,
     parallel_num: usize,
-) -> Result<Vec<(path::PathBuf, GPXTrackLog)>, String> {
+    clip_filter: &ClipFilter,
+    include_tracks: bool,
+) -> Result<Vec<Clip>, String> {
     let mut thread_handles = vec![];
     let dir_entries = fs::read_dir(dir_path);
     if dir_entries.is_err() {
@@ -170,7 +842,8 @@ fn process_directory(
     let thread_count = std::sync::Arc::new(AtomicUsize::new(0));
     let start_process_count = std::sync::Arc::new(AtomicUsize::new(0));
     for entry in dir_entries {
-        let output_dir = output_dir.clone();
+        let output_dir = output_dir.to_path_buf();
+        let clip_filter = clip_filter.clone();
 
         let process_count = start_process_count.fetch_add(1, AcqRel);
 
@@ -197,24 +870,40 @@ fn process_directory(
                     // directory
 
                     println!("Processing directory: {}", path.display());
-                    let result = process_directory(&path, &output_dir, parallel_num);
-                    if result.is_ok() {
-                        thread_count.fetch_sub(1, AcqRel);
-                        return Ok(result.unwrap());
-                    } else {
-                        let err_msg = format!(
-                            "Error processing directory {}: {}",
-                            path.display(),
-                            result.unwrap_err()
-                        );
+                    let result = process_directory(
+                        &path,
+                        &output_dir,
+                        parallel_num,
+                        &clip_filter,
+                        include_tracks,
+                    );
+                    thread_count.fetch_sub(1, AcqRel);
+                    result.map_err(|e| {
+                        let err_msg =
+                            format!("Error processing directory {}: {}", path.display(), e);
                         eprintln!("{}", err_msg);
-                        thread_count.fetch_sub(1, AcqRel);
-                        return Err(err_msg);
-                    }
+                        err_msg
+                    })
                 }
                 Err(_) => {
                     // file
 
+                    // ディレクトリからは AVI ファイルだけを読む。
+                    // このツールが書き出したトラックファイルは --include-tracks のときだけ読む
+                    let is_avi = path
+                        .extension()
+                        .and_then(|s| s.to_str())
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("avi"));
+                    let accepted = if is_avi {
+                        clip_filter.matches(&path)
+                    } else {
+                        include_tracks && is_track_file(&path)
+                    };
+                    if !accepted {
+                        thread_count.fetch_sub(1, AcqRel);
+                        return Ok(Vec::new());
+                    }
+
                     let mut results = Vec::new();
                     println!(
                         "[{}/{}] Processing file: {}",
@@ -222,22 +911,20 @@ fn process_directory(
                         dir_entry_count,
                         path.display()
                     );
-                    let logs = process_media_file(&path);
-                    if logs.is_err() {
-                        let err_msg = format!(
-                            "Error processing file {}: {}",
-                            path.display(),
-                            logs.unwrap_err()
-                        );
-                        eprintln!("{}", err_msg);
-                        thread_count.fetch_sub(1, AcqRel);
-                        return Err(err_msg);
-                    }
-                    let logs = logs.unwrap();
-                    results.push((path, logs.clone()));
+                    let clip = match process_media_file(&path) {
+                        Ok(clip) => clip,
+                        Err(e) => {
+                            let err_msg =
+                                format!("Error processing file {}: {}", path.display(), e);
+                            eprintln!("{}", err_msg);
+                            thread_count.fetch_sub(1, AcqRel);
+                            return Err(err_msg);
+                        }
+                    };
+                    results.push(clip);
 
                     thread_count.fetch_sub(1, AcqRel);
-                    return Ok(results);
+                    Ok(results)
                 }
             }
         });
@@ -248,14 +935,14 @@ fn process_directory(
         thread::sleep(std::time::Duration::from_millis(100));
     }
 
-    let mut gpx_track_logs = Vec::new();
+    let mut clips = Vec::new();
     for handle in thread_handles {
         // join() は Result<Result<String, io::Error>, _> を返す
         // 最初の unwrap はスレッドのパニックを処理
         // 次の unwrap_or_else は process_file 内の io::Error を処理 (エラー時はファイルパスを表示)
         match handle.join() {
             Ok(Ok(file_content)) => {
-                gpx_track_logs.extend(file_content);
+                clips.extend(file_content);
             }
             Ok(Err(e)) => {
                 eprintln!("Error processing file: {}", e); // ファイル処理エラー
@@ -266,33 +953,84 @@ fn process_directory(
         }
     }
 
-    Ok(gpx_track_logs)
+    Ok(clips)
 }
 
-fn process_media_file(file_path: &path::PathBuf) -> Result<GPXTrackLog, String> {
+fn is_same_file(a: &path::Path, b: &path::Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn is_track_file(path: &path::Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|ext| {
+            ["gpx", "polyline", "ztrk"]
+                .iter()
+                .any(|track| ext.eq_ignore_ascii_case(track))
+        })
+}
+
+fn process_media_file(file_path: &path::Path) -> Result<Clip, String> {
+    // 以前に出力した GPX などのトラックファイルも入力として扱う
+    let extension = file_path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "gpx" => {
+            let gpx = GPX::load(file_path, Cli::parse().get_timezone())?;
+            let metadata = gpx.metadata().cloned();
+            return process_track_file(file_path, gpx.into_tracks(), metadata);
+        }
+        "polyline" => {
+            let precision = Cli::parse().get_polyline_precision();
+            let tracks = Polyline::load(file_path, precision)?.into_tracks();
+            return process_track_file(file_path, tracks, None);
+        }
+        "ztrk" => {
+            let tracks = ZTRK::load(file_path)?.into_tracks();
+            return process_track_file(file_path, tracks, None);
+        }
+        _ => {}
+    }
+
     let mut gpx_tracklog = GPXTrackLog::new();
+    if let Some(name) = file_path.file_stem().and_then(|s| s.to_str()) {
+        gpx_tracklog.set_name(name);
+    }
+    if let Some(source) = file_path.file_name().and_then(|s| s.to_str()) {
+        gpx_tracklog.add_source(source);
+    }
 
     let file = ZDR055MediaData::new(file_path.to_str().unwrap());
     let stream_data = file
         .extract_stream_data()
         .map_err(|e| format!("Failed to extract stream data: {}", e))?;
 
+    let mut samples = Vec::new();
     let mut last_zdr_log = ZDR055PositionData::default();
-    let debug_mode = Cli::parse().is_debug_mode();
-    for line in stream_data.iter() {
+    let args = Cli::parse();
+    let debug_mode = args.is_debug_mode();
+    // (ストリーム内のインデックス, ログデータ, GPX の点)
+    let mut points = Vec::new();
+    for (index, line) in stream_data.iter().enumerate() {
         if debug_mode {
             println!("[DEBUG] {}", line);
         }
 
         // line は ZDR055 独自ログデータなので ZDR055PositionData に変換する
-        let log =
-            ZDR055PositionData::from_str(line).map_err(|e| format!("Failed to parse line: {}", e));
-
-        if log.is_err() {
-            eprintln!("Error parsing line: {}", log.unwrap_err());
-            continue;
-        }
-        let log = log.unwrap();
+        let log = match ZDR055PositionData::from_str(line) {
+            Ok(log) => log,
+            Err(e) => {
+                eprintln!("Error parsing line: Failed to parse line: {}", e);
+                continue;
+            }
+        };
+        samples.push((index, log.clone()));
         if !log.is_valid() {
             eprintln!("Invalid log data: {}", line);
             continue;
@@ -302,11 +1040,151 @@ fn process_media_file(file_path: &path::PathBuf) -> Result<GPXTrackLog, String>
             continue;
         }
         last_zdr_log = log.clone();
+        if gpx_tracklog.device().is_none() {
+            gpx_tracklog.set_device(log.to_gpx_device());
+        }
 
         // GPX 形式に変換して gpx_points に追加する
         let gpx_point = log.to_gpx_point();
+        points.push((index, log, gpx_point));
+    }
+
+    // 外れ値は前後の点と比べて判定するので、クリップの点をすべて読んでから除く
+    let reasons = match args.get_outlier_limits() {
+        Some((max_speed, max_acceleration)) => OutlierFilter::new(max_speed, max_acceleration)
+            .reject_reasons(
+                &points
+                    .iter()
+                    .map(|(_, _, point)| point.clone())
+                    .collect::<Vec<_>>(),
+            ),
+        None => vec![None; points.len()],
+    };
+    for ((index, log, gpx_point), reason) in points.into_iter().zip(reasons) {
+        if let Some(reason) = reason {
+            eprintln!(
+                "Dropped outlier: {} [{}] {}: {}",
+                file_path.display(),
+                index,
+                log.timestamp(),
+                reason
+            );
+            continue;
+        }
         gpx_tracklog.push(gpx_point);
     }
     // println!("Extracted data from {}", file_path.display());
-    Ok(gpx_tracklog)
+    Ok(Clip {
+        path: file_path.to_path_buf(),
+        log: gpx_tracklog,
+        samples,
+    })
+}
+
+fn process_track_file(
+    file_path: &path::Path,
+    tracks: Vec<GPXTrackLog>,
+    metadata: Option<GPXMetadata>,
+) -> Result<Clip, String> {
+    let mut gpx_tracklog = GPXTrackLog::new();
+    if let Some(name) = file_path.file_stem().and_then(|s| s.to_str()) {
+        gpx_tracklog.set_name(name);
+    }
+    if let Some(device) = metadata.as_ref().and_then(|m| m.device()) {
+        gpx_tracklog.set_device(device.clone());
+    }
+    for track in tracks {
+        gpx_tracklog.extend(track);
+    }
+
+    // トラック単位の元ファイル情報が無ければメタデータ、それも無ければ GPX ファイル自体を元とする
+    if gpx_tracklog.sources().is_empty() {
+        let sources = metadata.map(|m| m.sources().to_vec()).unwrap_or_default();
+        if sources.is_empty() {
+            if let Some(source) = file_path.file_name().and_then(|s| s.to_str()) {
+                gpx_tracklog.add_source(source);
+            }
+        }
+        for source in sources {
+            gpx_tracklog.add_source(&source);
+        }
+    }
+    Ok(Clip {
+        path: file_path.to_path_buf(),
+        log: gpx_tracklog,
+        samples: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(path: &str, time: Option<&str>) -> Clip {
+        let mut log = GPXTrackLog::new();
+        if let Some(time) = time {
+            log.push(GPXTrackPoint::new(35.0, 139.0, None, 0.0, time.to_string()));
+        }
+        Clip {
+            path: path::PathBuf::from(path),
+            log,
+            samples: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn writes_subtitles_for_paired_rear_clip() {
+        let dir = std::env::temp_dir().join(format!("zdr055_gpx_pair_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let front = dir.join("20250720_124810_F_S_Nor.AVI");
+        let rear = dir.join("20250720_124811_R_S_Nor.AVI");
+        let sample = ZDR055PositionData::from_str(
+            "ZDR055:2025-07-20 12:48:12 X:0.01 Y:-0.02 Z:1.00 T:0 12.3V N 35.680000 N 139.760000 E 40.0km/h E:0 M:1 EM:0 SA:2 V:13 S:120k 0A",
+        )
+        .unwrap();
+        let mut front_clip = clip(front.to_str().unwrap(), Some("2025-07-20 12:48:12"));
+        front_clip.samples.push((0, sample));
+
+        let clips = vec![front_clip];
+        let rear_clips = paired_rear_clips(&clips, &[(front.clone(), rear.clone())]);
+        assert_eq!(rear_clips.len(), 1);
+        assert_eq!(rear_clips[0].path, rear);
+        // 後ろのカメラのクリップが読めていれば作らない
+        assert!(paired_rear_clips(&rear_clips, &[(rear.clone(), rear.clone())]).is_empty());
+
+        let args = <Cli as clap::Parser>::parse_from(["zdr055_gpx", "x", "--subtitles", "srt"]);
+        for clip in clips.iter().chain(rear_clips.iter()) {
+            save_subtitles(clip, &args);
+        }
+        // 後ろの動画のファイル名の日時から数えるので 1 秒ずれる
+        let srt = fs::read_to_string(rear.with_extension("srt")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(srt.starts_with("1\n00:00:01,000 --> "));
+    }
+
+    #[test]
+    fn sorts_clips_by_first_timestamp() {
+        let mut clips = vec![
+            clip("20250720_120000_F_S_Nor.AVI", None),
+            clip("a/20250720_130000_F_S_Nor.AVI", Some("2025-07-20 12:00:00")),
+            clip("b/20250720_115900_F_S_Nor.AVI", Some("2025-07-20 13:00:00")),
+            clip("20250720_110000_F_S_Nor.AVI", None),
+            clip("unknown.AVI", None),
+        ];
+        sort_clips(&mut clips);
+        let paths = clips
+            .iter()
+            .map(|clip| clip.path.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "a/20250720_130000_F_S_Nor.AVI",
+                "b/20250720_115900_F_S_Nor.AVI",
+                "unknown.AVI",
+                "20250720_110000_F_S_Nor.AVI",
+                "20250720_120000_F_S_Nor.AVI",
+            ]
+        );
+    }
 }
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::gpx::track_point::GPXTrackPoint;
+use crate::zdr055::ZDR055PositionData;
+
+// 1ノット = 1.852 km/h
+const KMH_PER_KNOT: f64 = 1.852;
+
+// '$' と '*' の間の全バイトの XOR
+pub(crate) fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |acc, b| acc ^ b)
+}
+
+fn sentence(body: &str) -> String {
+    format!("${}*{:02X}\r\n", body, checksum(body))
+}
+
+// 度を NMEA の ddmm.mmmm 形式にする。 width は度の桁数
+fn degrees_minutes(degrees: f64, width: usize) -> String {
+    let degrees = degrees.abs();
+    let mut whole = degrees.trunc();
+    let mut minutes = (degrees - whole) * 60.0;
+    // 丸めで 60.0000 分になる場合は繰り上げる
+    if format!("{:.4}", minutes) == "60.0000" {
+        whole += 1.0;
+        minutes = 0.0;
+    }
+    format!("{:0width$}{:07.4}", whole as u32, minutes, width = width)
+}
+
+fn latitude(lat: f64) -> String {
+    format!(
+        "{},{}",
+        degrees_minutes(lat, 2),
+        if lat < 0.0 { "S" } else { "N" }
+    )
+}
+
+fn longitude(lon: f64) -> String {
+    format!(
+        "{},{}",
+        degrees_minutes(lon, 3),
+        if lon < 0.0 { "W" } else { "E" }
+    )
+}
+
+// 1秒分の $GPRMC / $GPGGA / $GPVTG
+#[derive(Debug, Clone)]
+pub(crate) struct NMEAEpoch {
+    time: DateTime<Tz>,
+    sentences: Vec<String>,
+}
+
+impl NMEAEpoch {
+    pub(crate) fn time(&self) -> DateTime<Tz> {
+        self.time
+    }
+
+    pub(crate) fn to_str(&self) -> String {
+        self.sentences.concat()
+    }
+}
+
+fn epoch(
+    sample: &ZDR055PositionData,
+    point: &GPXTrackPoint,
+    time: DateTime<Tz>,
+    course: Option<f64>,
+) -> NMEAEpoch {
+    let utc = time.with_timezone(&Utc);
+    let hms = utc.format("%H%M%S.00").to_string();
+    let position = format!("{},{}", latitude(point.lat()), longitude(point.lon()));
+    let knots = point.speed() / KMH_PER_KNOT;
+    let course = course.map(|c| format!("{:.1}", c)).unwrap_or_default();
+    let (status, quality, mode) = if sample.is_valid() {
+        ("A", 1, "A")
+    } else {
+        ("V", 0, "N")
+    };
+
+    let rmc = format!(
+        "GPRMC,{},{},{},{:.1},{},{},,,{}",
+        hms,
+        status,
+        position,
+        knots,
+        course,
+        utc.format("%d%m%y"),
+        mode
+    );
+    // 衛星数・HDOP・標高はログに無いので空欄にする
+    let gga = format!("GPGGA,{},{},{},,,,M,,M,,", hms, position, quality);
+    let vtg = format!(
+        "GPVTG,{},T,,M,{:.1},N,{:.1},K,{}",
+        course,
+        knots,
+        point.speed(),
+        mode
+    );
+
+    NMEAEpoch {
+        time,
+        sentences: vec![sentence(&rmc), sentence(&gga), sentence(&vtg)],
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct NMEA {
+    samples: Vec<ZDR055PositionData>,
+}
+
+impl NMEA {
+    pub(crate) fn new(samples: Vec<ZDR055PositionData>) -> Self {
+        NMEA { samples }
+    }
+
+    // 時刻の無いサンプルと、直前と同じ時刻のサンプルは出力しない
+    pub(crate) fn epochs(&self) -> Vec<NMEAEpoch> {
+        let mut epochs: Vec<NMEAEpoch> = Vec::new();
+        let mut last_point: Option<GPXTrackPoint> = None;
+        let mut course = None;
+        for sample in &self.samples {
+            let point = sample.to_gpx_point();
+            let time = match point.timestamp() {
+                Ok(time) => time,
+                Err(_) => continue,
+            };
+            if epochs.last().is_some_and(|last| last.time() == time) {
+                continue;
+            }
+            // 停止中は方位が定まらないので直前の値を使う
+            if let Some(last_point) = &last_point {
+                if last_point.distance_to(&point) > 0.0 {
+                    course = Some(last_point.bearing_to(&point));
+                }
+            }
+            epochs.push(epoch(sample, &point, time, course));
+            last_point = Some(point);
+        }
+        epochs
+    }
+
+    pub(crate) fn to_str(&self) -> String {
+        self.epochs().iter().map(|epoch| epoch.to_str()).collect()
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let nmea_string = self.to_str();
+        std::fs::write(path, nmea_string).map_err(|e| format!("Failed to write NMEA file: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_matches_reference_sentence() {
+        let body = "GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,";
+        assert_eq!(sentence(body), format!("${}*47\r\n", body));
+    }
+
+    #[test]
+    fn degrees_minutes_format() {
+        assert_eq!(latitude(35.5), "3530.0000,N");
+        assert_eq!(longitude(-139.25), "13915.0000,W");
+        assert_eq!(degrees_minutes(35.9999999999, 2), "3600.0000");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::gpx::track_log::{GPXSegmentGap, GPXTrackLog};
+use crate::gpx::track_point::GPXTrackPoint;
+
+// Google の Encoded Polyline Algorithm Format。 precision は小数点以下の桁数 (Google Maps は 5)
+pub(crate) fn encode(coordinates: &[(f64, f64)], precision: u32) -> String {
+    let factor = 10f64.powi(precision as i32);
+    let mut encoded = String::new();
+    let (mut last_lat, mut last_lon) = (0i64, 0i64);
+    for (lat, lon) in coordinates {
+        let (lat, lon) = ((lat * factor).round() as i64, (lon * factor).round() as i64);
+        encode_value(lat - last_lat, &mut encoded);
+        encode_value(lon - last_lon, &mut encoded);
+        (last_lat, last_lon) = (lat, lon);
+    }
+    encoded
+}
+
+fn encode_value(value: i64, encoded: &mut String) {
+    // 符号を最下位ビットに移し、5ビットずつ下位から並べる
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x20 {
+        encoded.push((((value & 0x1f) | 0x20) as u8 + 63) as char);
+        value >>= 5;
+    }
+    encoded.push((value as u8 + 63) as char);
+}
+
+pub(crate) fn decode(encoded: &str, precision: u32) -> Result<Vec<(f64, f64)>, String> {
+    let factor = 10f64.powi(precision as i32);
+    let mut bytes = encoded.bytes();
+    let mut coordinates = Vec::new();
+    let (mut lat, mut lon) = (0i64, 0i64);
+    while let Some(d_lat) = decode_value(&mut bytes)? {
+        let d_lon = decode_value(&mut bytes)?.ok_or("Missing longitude in polyline")?;
+        lat += d_lat;
+        lon += d_lon;
+        coordinates.push((lat as f64 / factor, lon as f64 / factor));
+    }
+    Ok(coordinates)
+}
+
+fn decode_value(bytes: &mut impl Iterator<Item = u8>) -> Result<Option<i64>, String> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = match bytes.next() {
+            Some(byte) => byte,
+            None if shift == 0 => return Ok(None),
+            None => return Err("Unexpected end of polyline".to_string()),
+        };
+        if !(63..=126).contains(&byte) || shift > 60 {
+            return Err(format!("Invalid character in polyline: {}", byte as char));
+        }
+        let chunk = (byte - 63) as u64;
+        value |= (chunk & 0x1f) << shift;
+        shift += 5;
+        if chunk < 0x20 {
+            break;
+        }
+    }
+    Ok(Some(((value >> 1) as i64) ^ -((value & 1) as i64)))
+}
+
+// 1行に1区間のポリラインを書く
+#[derive(Debug)]
+pub(crate) struct Polyline {
+    children: Vec<GPXTrackLog>,
+    segment_gap: GPXSegmentGap,
+    precision: u32,
+}
+
+impl Polyline {
+    pub(crate) fn new(children: Vec<GPXTrackLog>) -> Self {
+        Polyline {
+            children,
+            segment_gap: GPXSegmentGap::default(),
+            precision: 5,
+        }
+    }
+
+    pub(crate) fn set_segment_gap(&mut self, segment_gap: GPXSegmentGap) {
+        self.segment_gap = segment_gap;
+    }
+
+    pub(crate) fn set_precision(&mut self, precision: u32) {
+        self.precision = precision;
+    }
+
+    pub(crate) fn to_str(&self) -> String {
+        let mut lines = Vec::new();
+        for child in &self.children {
+            for segment in child.segments(&self.segment_gap) {
+                let coordinates = segment
+                    .iter()
+                    .map(|point| (point.lat(), point.lon()))
+                    .collect::<Vec<_>>();
+                lines.push(encode(&coordinates, self.precision) + "\n");
+            }
+        }
+        lines.concat()
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let polyline_string = self.to_str();
+        std::fs::write(path, polyline_string)
+            .map_err(|e| format!("Failed to write polyline file: {}", e))
+    }
+
+    // 位置しか持たないので、時刻と速度の無い点として読む
+    pub(crate) fn from_str(s: &str, precision: u32) -> Result<Self, String> {
+        let mut children = Vec::new();
+        for line in s
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+        {
+            let mut log = GPXTrackLog::new();
+            for (lat, lon) in decode(line, precision)? {
+                log.push(GPXTrackPoint::new(lat, lon, None, 0.0, String::new()));
+            }
+            children.push(log);
+        }
+        let mut polyline = Polyline::new(children);
+        polyline.set_precision(precision);
+        Ok(polyline)
+    }
+
+    pub(crate) fn load(path: &std::path::Path, precision: u32) -> Result<Self, String> {
+        let polyline_string = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read polyline file: {}", e))?;
+        Polyline::from_str(&polyline_string, precision)
+    }
+
+    pub(crate) fn into_tracks(self) -> Vec<GPXTrackLog> {
+        self.children
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Google のドキュメントにある例
+    const REFERENCE: &str = "_p~iF~ps|U_ulLnnqC_mqNvxq`@";
+    const REFERENCE_POINTS: [(f64, f64); 3] = [(38.5, -120.2), (40.7, -120.95), (43.252, -126.453)];
+
+    #[test]
+    fn encodes_reference_polyline() {
+        assert_eq!(encode(&REFERENCE_POINTS, 5), REFERENCE);
+    }
+
+    #[test]
+    fn decodes_reference_polyline() {
+        let decoded = decode(REFERENCE, 5).unwrap();
+        assert_eq!(decoded.len(), REFERENCE_POINTS.len());
+        for ((lat, lon), (expected_lat, expected_lon)) in decoded.iter().zip(REFERENCE_POINTS) {
+            assert!((lat - expected_lat).abs() < 1e-9);
+            assert!((lon - expected_lon).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn round_trips_track_log_with_precision() {
+        let mut log = GPXTrackLog::new();
+        for i in 0..50 {
+            log.push(GPXTrackPoint::new(
+                35.6812362 + i as f64 * 0.0001234567,
+                139.7671248 - i as f64 * 0.0000987654,
+                None,
+                40.0,
+                format!("2025-07-20 12:00:{:02}", i),
+            ));
+        }
+        for precision in [5, 6, 7] {
+            let mut polyline = Polyline::new(vec![log.clone()]);
+            polyline.set_precision(precision);
+            let tracks = Polyline::from_str(&polyline.to_str(), precision)
+                .unwrap()
+                .into_tracks();
+            assert_eq!(tracks.len(), 1);
+            let tolerance = 0.5 / 10f64.powi(precision as i32) + 1e-12;
+            for (decoded, original) in tracks[0].points().iter().zip(log.points()) {
+                assert!((decoded.lat() - original.lat()).abs() <= tolerance);
+                assert!((decoded.lon() - original.lon()).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_polyline() {
+        assert!(decode("_p~iF~ps|", 5).is_err());
+        assert!(decode("_p~iF", 5).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::io::Write;
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+use crate::nmea::NMEAEpoch;
+
+// 送り待ちがこの数を超えたクライアントは追いつけないものとして切断する
+const CLIENT_QUEUE_SIZE: usize = 64;
+
+// TCP のクライアント。書き込みはクライアントごとのスレッドで行い、遅いクライアントが他を待たせないようにする
+pub(crate) struct TcpClient {
+    peer: String,
+    sender: SyncSender<String>,
+    writer: JoinHandle<()>,
+}
+
+impl TcpClient {
+    fn new(mut stream: TcpStream) -> Self {
+        let peer = stream
+            .peer_addr()
+            .map(|peer| peer.to_string())
+            .unwrap_or_default();
+        eprintln!("Client connected: {}", peer);
+        let (sender, receiver) = mpsc::sync_channel::<String>(CLIENT_QUEUE_SIZE);
+        let writer = thread::spawn(move || {
+            for data in receiver {
+                if stream.write_all(data.as_bytes()).is_err() {
+                    break;
+                }
+            }
+        });
+        TcpClient {
+            peer,
+            sender,
+            writer,
+        }
+    }
+}
+
+// NMEA の送り先
+pub(crate) enum ReplaySink {
+    Stdout,
+    // 接続してきたクライアント全員に送る
+    Tcp(Arc<Mutex<Vec<TcpClient>>>),
+    Udp(UdpSocket, String),
+}
+
+impl ReplaySink {
+    // 最初のクライアントが接続するまで待ち、以降の接続は裏で受け付ける
+    pub(crate) fn tcp(address: &str) -> Result<Self, String> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+        eprintln!("Listening on tcp://{}, waiting for a client", address);
+        let (stream, _) = listener
+            .accept()
+            .map_err(|e| format!("Failed to accept a client: {}", e))?;
+        let clients = Arc::new(Mutex::new(vec![TcpClient::new(stream)]));
+        let accepted = Arc::clone(&clients);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let client = TcpClient::new(stream);
+                accepted.lock().unwrap().push(client);
+            }
+        });
+        Ok(ReplaySink::Tcp(clients))
+    }
+
+    pub(crate) fn udp(address: &str) -> Result<Self, String> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .map_err(|e| format!("Failed to open UDP socket: {}", e))?;
+        socket
+            .set_broadcast(true)
+            .map_err(|e| format!("Failed to enable UDP broadcast: {}", e))?;
+        Ok(ReplaySink::Udp(socket, address.to_string()))
+    }
+
+    fn send(&self, data: &str) -> Result<(), String> {
+        match self {
+            ReplaySink::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout
+                    .write_all(data.as_bytes())
+                    .and_then(|_| stdout.flush())
+                    .map_err(|e| format!("Failed to write to stdout: {}", e))
+            }
+            ReplaySink::Tcp(clients) => {
+                // 書き込みスレッドに渡すだけなので、ロック中に通信を待つことはない
+                clients.lock().unwrap().retain(|client| {
+                    match client.sender.try_send(data.to_string()) {
+                        Ok(()) => true,
+                        Err(TrySendError::Full(_)) => {
+                            eprintln!("Client too slow, disconnecting: {}", client.peer);
+                            false
+                        }
+                        Err(TrySendError::Disconnected(_)) => {
+                            eprintln!("Client disconnected: {}", client.peer);
+                            false
+                        }
+                    }
+                });
+                Ok(())
+            }
+            ReplaySink::Udp(socket, address) => socket
+                .send_to(data.as_bytes(), address.as_str())
+                .map(|_| ())
+                .map_err(|e| format!("Failed to send to {}: {}", address, e)),
+        }
+    }
+
+    // 送り待ちのデータを書き終えるまで待つ
+    pub(crate) fn close(self) {
+        if let ReplaySink::Tcp(clients) = self {
+            let clients = std::mem::take(&mut *clients.lock().unwrap());
+            for client in clients {
+                drop(client.sender);
+                let _ = client.writer.join();
+            }
+        }
+    }
+}
+
+// 先頭からの再生時刻。 max_gap を超えて記録が途切れているところは待たずに次へ進む
+fn schedule(times: &[DateTime<Tz>], max_gap: Duration) -> Vec<Duration> {
+    let mut offsets = Vec::with_capacity(times.len());
+    let mut offset = Duration::ZERO;
+    for (i, time) in times.iter().enumerate() {
+        if i > 0 {
+            let gap = (*time - times[i - 1]).to_std().unwrap_or_default();
+            if gap > max_gap {
+                eprintln!(
+                    "Skipping a gap of {} at {}",
+                    humantime::format_duration(gap),
+                    time.format("%Y-%m-%d %H:%M:%S")
+                );
+            } else {
+                offset += gap;
+            }
+        }
+        offsets.push(offset);
+    }
+    offsets
+}
+
+// 記録時刻の間隔を speed 倍速で再現しながら送る。 speed が 0 以下なら待たずに送る
+pub(crate) fn replay(
+    epochs: &[NMEAEpoch],
+    sink: &ReplaySink,
+    speed: f64,
+    max_gap: Duration,
+) -> Result<(), String> {
+    if epochs.is_empty() {
+        return Err("No position data to replay".to_string());
+    }
+    let times = epochs.iter().map(|epoch| epoch.time()).collect::<Vec<_>>();
+    let offsets = schedule(&times, max_gap);
+    let started = Instant::now();
+    for (epoch, offset) in epochs.iter().zip(offsets) {
+        if speed > 0.0 {
+            let target = started + offset.div_f64(speed);
+            let now = Instant::now();
+            if target > now {
+                thread::sleep(target - now);
+            }
+        }
+        sink.send(&epoch.to_str())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn skips_long_gaps() {
+        let time = |h: u32, m: u32, s: u32| {
+            chrono_tz::Asia::Tokyo
+                .with_ymd_and_hms(2025, 7, 20, h, m, s)
+                .unwrap()
+        };
+        let times = [
+            time(12, 0, 0),
+            time(12, 0, 1),
+            time(12, 1, 31),
+            // 夜まで止めていた
+            time(20, 0, 0),
+            time(20, 0, 1),
+            // 時刻が戻った場合は待たない
+            time(20, 0, 0),
+        ];
+        let offsets = schedule(&times, Duration::from_secs(120))
+            .iter()
+            .map(|offset| offset.as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 1, 91, 91, 92, 92]);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+use crate::gpx::track_log::GPXTrackLog;
+use crate::zdr055::ZDR055PositionData;
+
+// (元ファイル名, ストリーム内のインデックス, ログデータ)
+type Sample = (String, usize, ZDR055PositionData);
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum SplitPeriod {
+    Day,
+    // ISO 8601 の週 (月曜始まり)
+    Week,
+    Month,
+}
+
+impl SplitPeriod {
+    // 期間の名前。出力ファイル名に使う
+    pub(crate) fn key(&self, time: &DateTime<Tz>) -> String {
+        let format = match self {
+            SplitPeriod::Day => "%Y-%m-%d",
+            SplitPeriod::Week => "%G-W%V",
+            SplitPeriod::Month => "%Y-%m",
+        };
+        time.format(format).to_string()
+    }
+}
+
+// トリップをまたいで、期間ごとにトラックとサンプルを集める
+#[derive(Debug)]
+pub(crate) struct SplitOutput {
+    period: SplitPeriod,
+    timezone: Tz,
+    periods: BTreeMap<String, (Vec<GPXTrackLog>, Vec<Sample>)>,
+}
+
+impl SplitOutput {
+    pub(crate) fn new(period: SplitPeriod, timezone: Tz) -> Self {
+        SplitOutput {
+            period,
+            timezone,
+            periods: BTreeMap::new(),
+        }
+    }
+
+    fn key_of(&self, time: Result<DateTime<Tz>, String>) -> Option<String> {
+        time.ok()
+            .map(|time| self.period.key(&time.with_timezone(&self.timezone)))
+    }
+
+    // 期間の境目で track を分ける。時刻の無い点は直前の点と同じ期間にする
+    pub(crate) fn split_track(&self, track: &GPXTrackLog) -> Vec<(String, GPXTrackLog)> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        let mut current: Option<String> = None;
+        for (i, point) in track.points().iter().enumerate() {
+            let Some(key) = self.key_of(point.timestamp()) else {
+                continue;
+            };
+            match current.take() {
+                Some(previous) if previous != key => {
+                    pieces.push((previous, track.slice(start..i)));
+                    start = i;
+                }
+                _ => {}
+            }
+            current = Some(key);
+        }
+        if let Some(key) = current {
+            pieces.push((key, track.slice(start..track.points().len())));
+        }
+        pieces
+    }
+
+    pub(crate) fn add(&mut self, tracks: Vec<GPXTrackLog>, samples: Vec<Sample>) {
+        for track in &tracks {
+            for (key, piece) in self.split_track(track) {
+                self.periods.entry(key).or_default().0.push(piece);
+            }
+        }
+        // 時刻が読めるサンプルが出てくるまでは保留しておく
+        let mut pending = Vec::new();
+        let mut current: Option<String> = None;
+        for sample in samples {
+            if let Some(key) = self.key_of(sample.2.to_gpx_point().timestamp()) {
+                current = Some(key);
+            }
+            match &current {
+                Some(key) => {
+                    let entry = self.periods.entry(key.clone()).or_default();
+                    entry.1.append(&mut pending);
+                    entry.1.push(sample);
+                }
+                None => pending.push(sample),
+            }
+        }
+    }
+
+    pub(crate) fn into_periods(self) -> Vec<(String, Vec<GPXTrackLog>, Vec<Sample>)> {
+        self.periods
+            .into_iter()
+            .map(|(key, (tracks, samples))| (key, tracks, samples))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpx::track_point::GPXTrackPoint;
+
+    fn point(time: &str) -> GPXTrackPoint {
+        GPXTrackPoint::new(35.0, 139.0, None, 40.0, time.to_string())
+    }
+
+    #[test]
+    fn splits_at_local_midnight() {
+        let mut track = GPXTrackLog::new();
+        track.add_source("a.AVI");
+        track.push(point("2025-07-20 23:59:58"));
+        track.push(point("2025-07-20 23:59:59"));
+        track.add_source("b.AVI");
+        track.push(point("2025-07-21 00:00:00"));
+        track.push(point("2025-07-21 00:00:01"));
+
+        let tokyo = SplitOutput::new(SplitPeriod::Day, chrono_tz::Asia::Tokyo);
+        let pieces = tokyo.split_track(&track);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].0, "2025-07-20");
+        assert_eq!(pieces[1].0, "2025-07-21");
+        assert_eq!(pieces[1].1.points().len(), 2);
+        assert_eq!(pieces[1].1.sources(), ["b.AVI".to_string()]);
+
+        // UTC では全部 7/20 の昼
+        let utc = SplitOutput::new(SplitPeriod::Day, chrono_tz::UTC);
+        let pieces = utc.split_track(&track);
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(pieces[0].0, "2025-07-20");
+        assert_eq!(pieces[0].1.sources().len(), 2);
+    }
+
+    #[test]
+    fn names_weeks_and_months() {
+        let time = point("2025-12-29 12:00:00").timestamp().unwrap();
+        assert_eq!(SplitPeriod::Week.key(&time), "2026-W01");
+        assert_eq!(SplitPeriod::Month.key(&time), "2025-12");
+    }
+}
@@ -0,0 +1,302 @@
+use crate::gpx::track_log::{GPXSegmentGap, GPXTrackLog};
+use crate::gpx::track_point::GPXTrackPoint;
+use crate::json::{escape_json, json_number, json_time};
+
+// これ未満の速度 (km/h) は停止中とみなす
+pub(crate) const STOPPED_SPEED: f64 = 3.0;
+// この時間 (秒) 以上続けて止まっていたら1回の停止と数える
+const MIN_STOP_DURATION: f64 = 30.0;
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn json_location(point: Option<&GPXTrackPoint>) -> String {
+    match point {
+        Some(point) => format!("{{\"lat\":{:.7},\"lon\":{:.7}}}", point.lat(), point.lon()),
+        None => "null".to_string(),
+    }
+}
+
+// 点列の集計。区間の切れ目をまたぐ2点の間は距離にも時間にも数えない
+#[derive(Debug, Clone)]
+pub(crate) struct Summary {
+    name: String,
+    start: Option<GPXTrackPoint>,
+    end: Option<GPXTrackPoint>,
+    // m
+    distance: f64,
+    // 秒
+    driving_time: f64,
+    stopped_time: f64,
+    max_speed: Option<GPXTrackPoint>,
+    stops: usize,
+}
+
+impl Summary {
+    pub(crate) fn new(name: &str, points: &[GPXTrackPoint], gap: &GPXSegmentGap) -> Self {
+        let mut summary = Summary {
+            name: name.to_string(),
+            start: points.first().cloned(),
+            end: points.last().cloned(),
+            distance: 0.0,
+            driving_time: 0.0,
+            stopped_time: 0.0,
+            max_speed: None,
+            stops: 0,
+        };
+        for point in points {
+            if summary
+                .max_speed
+                .as_ref()
+                .is_none_or(|max| point.speed() > max.speed())
+            {
+                summary.max_speed = Some(point.clone());
+            }
+        }
+
+        // 走り出してから最後に止まるまでの間の停止だけを数える
+        let mut stopped_run = 0.0;
+        let mut moved = false;
+        for pair in points.windows(2) {
+            if gap.is_gap(&pair[0], &pair[1]) {
+                stopped_run = 0.0;
+                continue;
+            }
+            let dt = match (pair[0].timestamp(), pair[1].timestamp()) {
+                (Ok(a), Ok(b)) => (b - a).num_milliseconds() as f64 / 1000.0,
+                _ => continue,
+            };
+            summary.distance += pair[0].distance_to(&pair[1]);
+            if (pair[0].speed() + pair[1].speed()) / 2.0 < STOPPED_SPEED {
+                summary.stopped_time += dt;
+                stopped_run += dt;
+            } else {
+                if moved && stopped_run >= MIN_STOP_DURATION {
+                    summary.stops += 1;
+                }
+                moved = true;
+                summary.driving_time += dt;
+                stopped_run = 0.0;
+            }
+        }
+        summary
+    }
+
+    // 走行中の平均速度 (km/h)
+    fn average_speed(&self) -> Option<f64> {
+        if self.driving_time > 0.0 {
+            Some(self.distance / self.driving_time * 3.6)
+        } else {
+            None
+        }
+    }
+
+    fn to_table_row(&self, indent: &str) -> String {
+        let time = |point: Option<&GPXTrackPoint>| {
+            point
+                .and_then(|p| p.timestamp().ok())
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default()
+        };
+        let (max_speed, max_location) = match &self.max_speed {
+            Some(point) => (
+                format!("{:.1}", point.speed()),
+                format!("{:.5},{:.5}", point.lat(), point.lon()),
+            ),
+            None => (String::new(), String::new()),
+        };
+        format!(
+            "{:<32} {:<19} {:<19} {:>9.2} {:>9} {:>9} {:>7} {:>7} {:<19} {:>5}",
+            format!("{}{}", indent, self.name),
+            time(self.start.as_ref()),
+            time(self.end.as_ref()),
+            self.distance / 1000.0,
+            format_duration(self.driving_time),
+            format_duration(self.stopped_time),
+            self.average_speed()
+                .map(|speed| format!("{:.1}", speed))
+                .unwrap_or_default(),
+            max_speed,
+            max_location,
+            self.stops
+        )
+    }
+
+    fn to_json_fields(&self) -> String {
+        format!(
+            "\"name\":{},\"start_time\":{},\"end_time\":{},\"start\":{},\"end\":{},\"distance_m\":{},\"driving_s\":{},\"stopped_s\":{},\"average_speed_kmh\":{},\"max_speed_kmh\":{},\"max_speed_time\":{},\"max_speed_location\":{},\"stops\":{}",
+            escape_json(&self.name),
+            json_time(self.start.as_ref()),
+            json_time(self.end.as_ref()),
+            json_location(self.start.as_ref()),
+            json_location(self.end.as_ref()),
+            json_number(Some(self.distance)),
+            json_number(Some(self.driving_time)),
+            json_number(Some(self.stopped_time)),
+            json_number(self.average_speed()),
+            json_number(self.max_speed.as_ref().map(|p| p.speed())),
+            json_time(self.max_speed.as_ref()),
+            json_location(self.max_speed.as_ref()),
+            self.stops
+        )
+    }
+}
+
+#[derive(Debug)]
+struct TripSummary {
+    trip: Summary,
+    clips: Vec<Summary>,
+}
+
+// 出力したトリップごとの集計をまとめて表示・保存する
+#[derive(Debug)]
+pub(crate) struct Statistics {
+    segment_gap: GPXSegmentGap,
+    trips: Vec<TripSummary>,
+}
+
+impl Statistics {
+    pub(crate) fn new() -> Self {
+        Statistics {
+            segment_gap: GPXSegmentGap::default(),
+            trips: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_segment_gap(&mut self, segment_gap: GPXSegmentGap) {
+        self.segment_gap = segment_gap;
+    }
+
+    // tracks 全体を1つのトリップ、その中の元ファイルごとの範囲をクリップとして集計する
+    pub(crate) fn add_trip(&mut self, name: &str, tracks: &[GPXTrackLog]) {
+        let points = tracks
+            .iter()
+            .flat_map(|track| track.points().iter().cloned())
+            .collect::<Vec<GPXTrackPoint>>();
+        let trip = Summary::new(name, &points, &self.segment_gap);
+        let mut clips = Vec::new();
+        for track in tracks {
+            for range in track.clip_ranges() {
+                let name = track.sources_in(&range).join(",");
+                clips.push(Summary::new(
+                    &name,
+                    &track.points()[range],
+                    &self.segment_gap,
+                ));
+            }
+        }
+        self.trips.push(TripSummary { trip, clips });
+    }
+
+    pub(crate) fn to_table(&self) -> String {
+        let mut lines = vec![format!(
+            "{:<32} {:<19} {:<19} {:>9} {:>9} {:>9} {:>7} {:>7} {:<19} {:>5}",
+            "Name",
+            "Start",
+            "End",
+            "Dist km",
+            "Driving",
+            "Stopped",
+            "Avg",
+            "Max",
+            "Max at",
+            "Stops"
+        )];
+        for trip in &self.trips {
+            lines.push(trip.trip.to_table_row(""));
+            // 1クリップだけのトリップはクリップの行を省く
+            if trip.clips.len() > 1 {
+                for clip in &trip.clips {
+                    lines.push(clip.to_table_row("  "));
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        let trips = self
+            .trips
+            .iter()
+            .map(|trip| {
+                let clips = trip
+                    .clips
+                    .iter()
+                    .map(|clip| format!("{{{}}}", clip.to_json_fields()))
+                    .collect::<Vec<String>>();
+                format!(
+                    "{{{},\"clips\":[{}]}}",
+                    trip.trip.to_json_fields(),
+                    clips.join(",")
+                )
+            })
+            .collect::<Vec<String>>();
+        format!("{{\"trips\":[{}]}}\n", trips.join(",\n"))
+    }
+
+    pub(crate) fn save_json(&self, path: &std::path::Path) -> Result<(), String> {
+        std::fs::write(path, self.to_json())
+            .map_err(|e| format!("Failed to write statistics file: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lon: f64, second: usize, speed: f64) -> GPXTrackPoint {
+        GPXTrackPoint::new(
+            35.0,
+            lon,
+            None,
+            speed,
+            format!("2025-07-20 12:{:02}:{:02}", second / 60, second % 60),
+        )
+    }
+
+    #[test]
+    fn summarizes_driving_and_stops() {
+        let mut points = Vec::new();
+        let mut lon = 139.0;
+        // 停止 20 秒、走行 60 秒、停止 40 秒、走行 60 秒、停止 19 秒
+        for second in 0..200 {
+            let moving = (20..80).contains(&second) || (120..180).contains(&second);
+            let speed = if moving { 36.0 } else { 0.0 };
+            if moving {
+                lon += 0.00011;
+            }
+            points.push(point(lon, second, if second == 150 { 50.0 } else { speed }));
+        }
+        let summary = Summary::new("trip", &points, &GPXSegmentGap::default());
+        assert_eq!(summary.stops, 1);
+        assert_eq!(summary.driving_time, 122.0);
+        assert_eq!(summary.stopped_time, 77.0);
+        assert!(
+            (summary.distance - 1200.0).abs() < 20.0,
+            "{}",
+            summary.distance
+        );
+        assert_eq!(summary.max_speed.unwrap().speed(), 50.0);
+    }
+
+    #[test]
+    fn skips_segment_gaps() {
+        let points = vec![
+            point(139.0, 0, 36.0),
+            point(139.00011, 1, 36.0),
+            point(139.1, 600, 36.0),
+            point(139.10011, 601, 36.0),
+        ];
+        let gap = GPXSegmentGap::new(Some(std::time::Duration::from_secs(120)), None);
+        let summary = Summary::new("trip", &points, &gap);
+        assert_eq!(summary.driving_time, 2.0);
+        assert!(summary.distance < 30.0);
+    }
+}
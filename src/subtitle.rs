@@ -0,0 +1,230 @@
+mod ass;
+
+use chrono::{DateTime, Duration, NaiveDateTime};
+use chrono_tz::Tz;
+
+use crate::gpx::track_point::GPXTrackPoint;
+use crate::zdr055::ZDR055PositionData;
+
+pub(crate) const DEFAULT_TEMPLATE: &str =
+    "{date} {time}  {speed} km/h\\nG {x_accel} / {y_accel} / {z_accel}  {event}\\n{lat}, {lon}";
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum SubtitleFormat {
+    Srt,
+    Vtt,
+    // 動画に焼き込む用のオーバーレイ
+    Ass,
+}
+
+impl SubtitleFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Ass => "ass",
+        }
+    }
+}
+
+fn cue_time(offset: Duration, separator: char) -> String {
+    let ms = offset.num_milliseconds().max(0);
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+// テンプレートの {name} をサンプルの値で置き換える。 "\n" は改行にする
+fn render(template: &str, point: &GPXTrackPoint, time: DateTime<Tz>) -> String {
+    let [x, y, z] = point.acceleration().unwrap_or_default();
+    let values = [
+        ("date", time.format("%Y-%m-%d").to_string()),
+        ("time", time.format("%H:%M:%S").to_string()),
+        ("speed", format!("{:.0}", point.speed())),
+        ("lat", format!("{:.6}", point.lat())),
+        ("lon", format!("{:.6}", point.lon())),
+        ("x_accel", format!("{:+.2}", x)),
+        ("y_accel", format!("{:+.2}", y)),
+        ("z_accel", format!("{:+.2}", z)),
+        ("g", format!("{:.2}", (x * x + y * y + z * z).sqrt())),
+        ("event", point.event().unwrap_or_default().to_string()),
+        (
+            "voltage",
+            format!("{:.1}", point.voltage().unwrap_or_default()),
+        ),
+    ];
+    let mut text = template.replace("\\n", "\n");
+    for (name, value) in values {
+        text = text.replace(&format!("{{{}}}", name), &value);
+    }
+    text
+}
+
+#[derive(Debug)]
+pub(crate) struct Subtitle {
+    samples: Vec<ZDR055PositionData>,
+    template: String,
+    // 動画の先頭の時刻 (ドラレコの時計)。分からなければ最初のサンプルの時刻とする
+    start: Option<NaiveDateTime>,
+}
+
+impl Subtitle {
+    pub(crate) fn new(samples: Vec<ZDR055PositionData>) -> Self {
+        Subtitle {
+            samples,
+            template: DEFAULT_TEMPLATE.to_string(),
+            start: None,
+        }
+    }
+
+    pub(crate) fn set_template(&mut self, template: &str) {
+        self.template = template.to_string();
+    }
+
+    pub(crate) fn set_start(&mut self, start: NaiveDateTime) {
+        self.start = Some(start);
+    }
+
+    // 時刻の無いサンプルと、直前と同じ時刻のサンプルは除く
+    fn timed_points(&self) -> Vec<(GPXTrackPoint, DateTime<Tz>)> {
+        let mut points: Vec<(GPXTrackPoint, DateTime<Tz>)> = Vec::new();
+        for sample in &self.samples {
+            let point = sample.to_gpx_point();
+            let time = match point.timestamp() {
+                Ok(time) => time,
+                Err(_) => continue,
+            };
+            if points.last().is_some_and(|(_, last)| *last == time) {
+                continue;
+            }
+            points.push((point, time));
+        }
+        points
+    }
+
+    // 1サンプルを1キューとし、動画の先頭からの経過時間で表示する
+    pub(crate) fn to_str(&self, format: SubtitleFormat) -> String {
+        let points = self.timed_points();
+        let start = self
+            .start
+            .or_else(|| points.first().map(|(_, time)| time.naive_local()));
+        let (header, separator) = match format {
+            SubtitleFormat::Srt => ("", ','),
+            SubtitleFormat::Vtt => ("WEBVTT\n\n", '.'),
+            SubtitleFormat::Ass => return ass::to_ass_string(&points, start),
+        };
+
+        let mut cues = Vec::new();
+        if let Some(start) = start {
+            for (i, (point, time)) in points.iter().enumerate() {
+                // 次のサンプルまで表示する。最後のサンプルは1秒間
+                let end = match points.get(i + 1) {
+                    Some((_, next)) => *next,
+                    None => *time + Duration::seconds(1),
+                };
+                let mut text = render(&self.template, point, *time);
+                if format == SubtitleFormat::Vtt {
+                    text = text
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;");
+                }
+                cues.push(format!(
+                    "{}\n{} --> {}\n{}\n",
+                    i + 1,
+                    cue_time(time.naive_local() - start, separator),
+                    cue_time(end.naive_local() - start, separator),
+                    text
+                ));
+            }
+        }
+        format!("{}{}", header, cues.join("\n"))
+    }
+
+    pub(crate) fn save(
+        &self,
+        path: &std::path::Path,
+        format: SubtitleFormat,
+    ) -> Result<(), String> {
+        let subtitle_string = self.to_str(format);
+        std::fs::write(path, subtitle_string)
+            .map_err(|e| format!("Failed to write subtitle file: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn subtitle(times: &[&str]) -> Subtitle {
+        let mut subtitle = Subtitle::new(
+            times
+                .iter()
+                .map(|time| {
+                    ZDR055PositionData::from_str(&format!(
+                        "ZDR055:2025-07-20 {} X:0.01 Y:-0.02 Z:1.00 T:0 12.3V N 35.680000 N 139.760000 E 40.0km/h E:0 M:1 EM:0 SA:2 V:13 S:120k 0A",
+                        time
+                    ))
+                    .unwrap()
+                })
+                .collect(),
+        );
+        subtitle.set_template("{time}");
+        subtitle
+    }
+
+    fn start(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2025-07-20 {}", time), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn anchors_cues_to_clip_start() {
+        // GPS の測位が録画開始から3秒遅れた場合
+        let mut subtitle = subtitle(&["12:48:13", "12:48:14", "12:48:14", "12:48:15"]);
+        subtitle.set_start(start("12:48:10"));
+        assert_eq!(
+            subtitle.to_str(SubtitleFormat::Srt),
+            "1\n00:00:03,000 --> 00:00:04,000\n12:48:13\n\n\
+2\n00:00:04,000 --> 00:00:05,000\n12:48:14\n\n\
+3\n00:00:05,000 --> 00:00:06,000\n12:48:15\n"
+        );
+        assert!(subtitle
+            .to_str(SubtitleFormat::Vtt)
+            .starts_with("WEBVTT\n\n1\n00:00:03.000 --> 00:00:04.000\n12:48:13\n"));
+    }
+
+    #[test]
+    fn falls_back_to_first_sample() {
+        let subtitle = subtitle(&["12:48:13", "12:48:14"]);
+        assert!(subtitle
+            .to_str(SubtitleFormat::Srt)
+            .starts_with("1\n00:00:00,000 --> 00:00:01,000\n"));
+    }
+
+    #[test]
+    fn anchors_ass_events_to_clip_start() {
+        let mut subtitle = subtitle(&["12:48:13", "12:48:14"]);
+        subtitle.set_start(start("12:48:10"));
+        let s = subtitle.to_str(SubtitleFormat::Ass);
+        let speeds = s
+            .lines()
+            .filter(|line| line.contains(",Speed,"))
+            .map(|line| line.split(',').take(3).collect::<Vec<_>>().join(","))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            speeds,
+            vec![
+                "Dialogue: 2,0:00:03.00,0:00:04.00",
+                "Dialogue: 2,0:00:04.00,0:00:05.00"
+            ]
+        );
+        // ミニマップなどは動画の先頭から最後のサンプルまで表示する
+        assert!(s.contains("Dialogue: 0,0:00:00.00,0:00:05.00,Panel,"));
+    }
+}
@@ -0,0 +1,367 @@
+use chrono::{DateTime, Duration, NaiveDateTime};
+use chrono_tz::Tz;
+
+use crate::gpx::track_point::GPXTrackPoint;
+
+// 1080p の動画を想定した座標系。 ffmpeg の ass フィルタが実際の解像度に合わせて拡大縮小する
+const PLAY_RES_X: f64 = 1920.0;
+const PLAY_RES_Y: f64 = 1080.0;
+const MARGIN: f64 = 40.0;
+
+// 右上のミニマップ
+const MAP_SIZE: f64 = 320.0;
+const MAP_PADDING: f64 = 16.0;
+
+// 左下の G インジケーター。外周の円が 1G
+const G_RADIUS: f64 = 110.0;
+const G_FULL_SCALE: f64 = 1.0;
+
+// ASS の時刻は H:MM:SS.cc (1/100 秒)
+fn ass_time(offset: Duration) -> String {
+    let cs = offset.num_milliseconds().max(0) / 10;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        cs / 360_000,
+        cs / 6_000 % 60,
+        cs / 100 % 60,
+        cs % 100
+    )
+}
+
+// 中心 (x, y)、半径 r の円を4本のベジエ曲線で描く
+fn circle(x: f64, y: f64, r: f64) -> String {
+    let k = r * 0.5523;
+    format!(
+        "m {x:.1} {t:.1} b {xr1:.1} {t:.1} {r2:.1} {yk1:.1} {r2:.1} {y:.1} b {r2:.1} {yk2:.1} {xr1:.1} {b:.1} {x:.1} {b:.1} b {xl1:.1} {b:.1} {l2:.1} {yk2:.1} {l2:.1} {y:.1} b {l2:.1} {yk1:.1} {xl1:.1} {t:.1} {x:.1} {t:.1}",
+        x = x,
+        y = y,
+        t = y - r,
+        b = y + r,
+        l2 = x - r,
+        r2 = x + r,
+        xr1 = x + k,
+        xl1 = x - k,
+        yk1 = y - k,
+        yk2 = y + k,
+    )
+}
+
+fn rectangle(left: f64, top: f64, right: f64, bottom: f64) -> String {
+    format!(
+        "m {:.1} {:.1} l {:.1} {:.1} {:.1} {:.1} {:.1} {:.1}",
+        left, top, right, top, right, bottom, left, bottom
+    )
+}
+
+// 緯度経度をミニマップ上の画面座標に変換する
+struct MapProjection {
+    min_x: f64,
+    max_y: f64,
+    scale: f64,
+    offset_x: f64,
+    offset_y: f64,
+    cos_lat: f64,
+}
+
+impl MapProjection {
+    fn new(points: &[(GPXTrackPoint, DateTime<Tz>)]) -> Option<Self> {
+        let (first, _) = points.first()?;
+        let cos_lat = first.lat().to_radians().cos();
+        let mut min_x = f64::MAX;
+        let mut max_x = f64::MIN;
+        let mut min_y = f64::MAX;
+        let mut max_y = f64::MIN;
+        for (point, _) in points {
+            min_x = min_x.min(point.lon() * cos_lat);
+            max_x = max_x.max(point.lon() * cos_lat);
+            min_y = min_y.min(point.lat());
+            max_y = max_y.max(point.lat());
+        }
+
+        // 縦横比を保ったまま枠に収め、余った方向は中央に寄せる
+        let inner = MAP_SIZE - MAP_PADDING * 2.0;
+        let span = (max_x - min_x).max(max_y - min_y);
+        let scale = if span > 0.0 { inner / span } else { 0.0 };
+        let left = PLAY_RES_X - MARGIN - MAP_SIZE + MAP_PADDING;
+        let top = MARGIN + MAP_PADDING;
+        Some(MapProjection {
+            min_x,
+            max_y,
+            scale,
+            offset_x: left + (inner - (max_x - min_x) * scale) / 2.0,
+            offset_y: top + (inner - (max_y - min_y) * scale) / 2.0,
+            cos_lat,
+        })
+    }
+
+    fn project(&self, point: &GPXTrackPoint) -> (f64, f64) {
+        (
+            self.offset_x + (point.lon() * self.cos_lat - self.min_x) * self.scale,
+            self.offset_y + (self.max_y - point.lat()) * self.scale,
+        )
+    }
+}
+
+// 描画はすべて \an7\pos(0,0) を原点とした画面座標で書く
+fn drawing(layer: u32, start: &str, end: &str, style: &str, tags: &str, commands: &str) -> String {
+    format!(
+        "Dialogue: {},{},{},{},,0,0,0,,{{\\an7\\pos(0,0){}\\p1}}{}{{\\p0}}\n",
+        layer, start, end, style, tags, commands
+    )
+}
+
+// start は動画の先頭の時刻。各サンプルはそこからの経過時間に表示する
+pub(crate) fn to_ass_string(
+    points: &[(GPXTrackPoint, DateTime<Tz>)],
+    start: Option<NaiveDateTime>,
+) -> String {
+    let mut events = String::new();
+    if let (Some(start), Some((_, last)), Some(map)) =
+        (start, points.last(), MapProjection::new(points))
+    {
+        let clip_start = ass_time(Duration::zero());
+        let clip_end = ass_time(last.naive_local() - start + Duration::seconds(1));
+
+        // クリップ全体で表示し続けるもの: ミニマップの背景と経路、 G インジケーターの目盛り
+        let map_left = PLAY_RES_X - MARGIN - MAP_SIZE;
+        events.push_str(&drawing(
+            0,
+            &clip_start,
+            &clip_end,
+            "Panel",
+            "",
+            &rectangle(map_left, MARGIN, map_left + MAP_SIZE, MARGIN + MAP_SIZE),
+        ));
+        let route = points
+            .iter()
+            .map(|(point, _)| {
+                let (x, y) = map.project(point);
+                format!("{:.1} {:.1}", x, y)
+            })
+            .collect::<Vec<String>>();
+        // 線の描画命令は無いので、往路と復路で閉じた細い図形として縁取りで線を描く
+        let mut route_path = format!("m {} l {}", route[0], route.join(" "));
+        for p in route.iter().rev() {
+            route_path.push_str(&format!(" {}", p));
+        }
+        events.push_str(&drawing(
+            1,
+            &clip_start,
+            &clip_end,
+            "Route",
+            "",
+            &route_path,
+        ));
+
+        let g_x = MARGIN + G_RADIUS;
+        let g_y = PLAY_RES_Y - MARGIN - G_RADIUS;
+        events.push_str(&drawing(
+            0,
+            &clip_start,
+            &clip_end,
+            "Panel",
+            "",
+            &circle(g_x, g_y, G_RADIUS + MAP_PADDING),
+        ));
+        events.push_str(&drawing(
+            1,
+            &clip_start,
+            &clip_end,
+            "Route",
+            "",
+            &format!(
+                "{} {}",
+                circle(g_x, g_y, G_RADIUS),
+                circle(g_x, g_y, G_RADIUS / 2.0)
+            ),
+        ));
+
+        // サンプルごとに切り替えるもの: 現在地、 G の点、速度
+        for (i, (point, time)) in points.iter().enumerate() {
+            let end = match points.get(i + 1) {
+                Some((_, next)) => *next,
+                None => *time + Duration::seconds(1),
+            };
+            let (cue_start, cue_end) = (
+                ass_time(time.naive_local() - start),
+                ass_time(end.naive_local() - start),
+            );
+
+            let (x, y) = map.project(point);
+            events.push_str(&drawing(
+                2,
+                &cue_start,
+                &cue_end,
+                "Marker",
+                "",
+                &circle(x, y, 8.0),
+            ));
+
+            if let Some([ax, ay, _]) = point.acceleration() {
+                // 横方向 (X) を左右、前後方向 (Y) を上下に取り、外周を超える値は外周に留める
+                let (mut dx, mut dy) = (ax / G_FULL_SCALE, -ay / G_FULL_SCALE);
+                let length = (dx * dx + dy * dy).sqrt();
+                if length > 1.0 {
+                    dx /= length;
+                    dy /= length;
+                }
+                events.push_str(&drawing(
+                    2,
+                    &cue_start,
+                    &cue_end,
+                    "Marker",
+                    "",
+                    &circle(g_x + dx * G_RADIUS, g_y + dy * G_RADIUS, 10.0),
+                ));
+                // 下は画面の端なので、値は G インジケーターの上に書く
+                events.push_str(&format!(
+                    "Dialogue: 2,{},{},Label,,0,0,0,,{{\\an5\\pos({:.0},{:.0})}}{:.2} G\n",
+                    cue_start,
+                    cue_end,
+                    g_x,
+                    g_y - G_RADIUS - MAP_PADDING - 24.0,
+                    (ax * ax + ay * ay).sqrt()
+                ));
+            }
+
+            events.push_str(&format!(
+                "Dialogue: 2,{},{},Speed,,0,0,0,,{{\\an3\\pos({:.0},{:.0})}}{:.0}{{\\fs48}} km/h\\N{{\\fs36}}{}\n",
+                cue_start,
+                cue_end,
+                PLAY_RES_X - MARGIN,
+                PLAY_RES_Y - MARGIN,
+                point.speed(),
+                time.format("%Y-%m-%d %H:%M:%S")
+            ));
+        }
+    }
+
+    format!(
+        "[Script Info]\n\
+ScriptType: v4.00+\n\
+PlayResX: {:.0}\n\
+PlayResY: {:.0}\n\
+WrapStyle: 2\n\
+ScaledBorderAndShadow: yes\n\
+\n\
+[V4+ Styles]\n\
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+Style: Speed,Sans,96,&H00FFFFFF,&H00FFFFFF,&H00000000,&H80000000,-1,0,0,0,100,100,0,0,1,4,2,3,0,0,0,1\n\
+Style: Label,Sans,32,&H00FFFFFF,&H00FFFFFF,&H00000000,&H80000000,-1,0,0,0,100,100,0,0,1,3,0,5,0,0,0,1\n\
+Style: Panel,Sans,20,&H80000000,&H80000000,&H80000000,&H00000000,0,0,0,0,100,100,0,0,1,0,0,7,0,0,0,1\n\
+Style: Route,Sans,20,&HFF000000,&HFF000000,&H00FFFFFF,&H00000000,0,0,0,0,100,100,0,0,1,2,0,7,0,0,0,1\n\
+Style: Marker,Sans,20,&H000000FF,&H000000FF,&H00FFFFFF,&H00000000,0,0,0,0,100,100,0,0,1,2,0,7,0,0,0,1\n\
+\n\
+[Events]\n\
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+{}",
+        PLAY_RES_X, PLAY_RES_Y, events
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timed(
+        lat: f64,
+        lon: f64,
+        second: u32,
+        acceleration: Option<[f64; 3]>,
+    ) -> (GPXTrackPoint, DateTime<Tz>) {
+        let mut point = GPXTrackPoint::new(
+            lat,
+            lon,
+            None,
+            40.0,
+            format!("2025-07-20 12:48:{:02}", second),
+        );
+        point.set_acceleration(acceleration);
+        let time = point.timestamp().unwrap();
+        (point, time)
+    }
+
+    fn assert_near((x, y): (f64, f64), (ex, ey): (f64, f64)) {
+        assert!(
+            (x - ex).abs() < 1e-6 && (y - ey).abs() < 1e-6,
+            "({}, {}) != ({}, {})",
+            x,
+            y,
+            ex,
+            ey
+        );
+    }
+
+    #[test]
+    fn projects_route_into_map() {
+        // 東西に長い経路
+        let points = [
+            timed(35.0, 139.0, 10, None),
+            timed(35.0, 139.02, 11, None),
+            timed(35.005, 139.01, 12, None),
+        ];
+        let map = MapProjection::new(&points).unwrap();
+        let inner = MAP_SIZE - MAP_PADDING * 2.0;
+        let left = PLAY_RES_X - MARGIN - MAP_SIZE + MAP_PADDING;
+        let top = MARGIN + MAP_PADDING;
+
+        // 長い方の東西が枠の幅いっぱいになり、南北は同じ縮尺で上下の中央に寄る
+        let west = map.project(&points[0].0);
+        let east = map.project(&points[1].0);
+        let north = map.project(&points[2].0);
+        assert!((west.0 - left).abs() < 1e-6);
+        assert!((east.0 - (left + inner)).abs() < 1e-6);
+        assert!((west.1 - east.1).abs() < 1e-6);
+        assert!((north.0 - (left + inner / 2.0)).abs() < 1e-6);
+        let aspect = (west.1 - north.1) / (east.0 - west.0);
+        let expected = 0.005 / (0.02 * 35.0_f64.to_radians().cos());
+        assert!((aspect - expected).abs() < 1e-9);
+        assert!((west.1 + north.1 - (top * 2.0 + inner)).abs() < 1e-6);
+
+        // 1点だけなら枠の中央に置く
+        let map = MapProjection::new(&points[..1]).unwrap();
+        assert_near(
+            map.project(&points[0].0),
+            (left + inner / 2.0, top + inner / 2.0),
+        );
+    }
+
+    #[test]
+    fn draws_g_indicator_for_acceleration() {
+        let points = [
+            timed(35.0, 139.0, 11, Some([0.3, 0.4, 1.0])),
+            // 外周を超える値は外周に留める
+            timed(35.0, 139.0001, 12, Some([1.2, 1.6, 1.0])),
+        ];
+        let start =
+            NaiveDateTime::parse_from_str("2025-07-20 12:48:10", "%Y-%m-%d %H:%M:%S").unwrap();
+        let s = to_ass_string(&points, Some(start));
+
+        let g_x = MARGIN + G_RADIUS;
+        let g_y = PLAY_RES_Y - MARGIN - G_RADIUS;
+        let dot = drawing(
+            2,
+            "0:00:01.00",
+            "0:00:02.00",
+            "Marker",
+            "",
+            &circle(g_x + 0.3 * G_RADIUS, g_y - 0.4 * G_RADIUS, 10.0),
+        );
+        assert!(dot.contains("\\p1}m 183.0 876.0 b "));
+        assert!(s.contains(&dot));
+        assert!(s.contains(&drawing(
+            2,
+            "0:00:02.00",
+            "0:00:03.00",
+            "Marker",
+            "",
+            &circle(g_x + 0.6 * G_RADIUS, g_y - 0.8 * G_RADIUS, 10.0),
+        )));
+        assert!(s.contains(
+            "Dialogue: 2,0:00:01.00,0:00:02.00,Label,,0,0,0,,{\\an5\\pos(150,780)}0.50 G\n"
+        ));
+        assert!(s.contains(
+            "Dialogue: 2,0:00:02.00,0:00:03.00,Label,,0,0,0,,{\\an5\\pos(150,780)}2.00 G\n"
+        ));
+    }
+}
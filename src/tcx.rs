@@ -0,0 +1,205 @@
+use chrono::Utc;
+
+use crate::gpx::escape_xml;
+use crate::gpx::track_log::GPXTrackLog;
+use crate::gpx::track_point::GPXTrackPoint;
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+fn tcx_time(point: &GPXTrackPoint) -> Option<String> {
+    let timestamp = point.timestamp().ok()?;
+    Some(
+        timestamp
+            .with_timezone(&Utc)
+            .format(TIME_FORMAT)
+            .to_string(),
+    )
+}
+
+#[derive(Debug)]
+pub(crate) struct TCX {
+    children: Vec<GPXTrackLog>,
+}
+
+impl TCX {
+    pub(crate) fn new(children: Vec<GPXTrackLog>) -> Self {
+        TCX { children }
+    }
+
+    pub(crate) fn to_str(&self) -> String {
+        let activities = self
+            .children
+            .iter()
+            .filter_map(activity)
+            .collect::<Vec<String>>();
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\" xmlns:ns3=\"http://www.garmin.com/xmlschemas/ActivityExtension/v2\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2 http://www.garmin.com/xmlschemas/TrainingCenterDatabasev2.xsd\">\n<Activities>\n{}</Activities>\n</TrainingCenterDatabase>\n",
+            activities.join("")
+        )
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let tcx_string = self.to_str();
+        std::fs::write(path, tcx_string).map_err(|e| format!("Failed to write TCX file: {}", e))
+    }
+}
+
+// 1つのトラックを1つの Activity とし、元のクリップごとに Lap を分ける
+fn activity(child: &GPXTrackLog) -> Option<String> {
+    let id = child.points().iter().find_map(tcx_time)?;
+
+    let mut laps = Vec::new();
+    let mut distance = 0.0;
+    let mut last_point: Option<&GPXTrackPoint> = None;
+    for range in child.clip_ranges() {
+        let mut trackpoints = Vec::new();
+        let mut lap_start: Option<(String, &GPXTrackPoint, f64)> = None;
+        let mut lap_end: Option<&GPXTrackPoint> = None;
+        let mut max_speed: f64 = 0.0;
+        for point in child.points()[range.clone()].iter() {
+            let time = match tcx_time(point) {
+                Some(time) => time,
+                None => continue,
+            };
+            if let Some(last_point) = last_point {
+                distance += last_point.distance_to(point);
+            }
+            last_point = Some(point);
+            if lap_start.is_none() {
+                lap_start = Some((time.clone(), point, distance));
+            }
+            lap_end = Some(point);
+            max_speed = max_speed.max(point.speed() / 3.6);
+
+            let altitude = match point.ele() {
+                Some(ele) => format!("<AltitudeMeters>{:.2}</AltitudeMeters>", ele),
+                None => String::new(),
+            };
+            trackpoints.push(format!(
+                "<Trackpoint><Time>{}</Time><Position><LatitudeDegrees>{:.7}</LatitudeDegrees><LongitudeDegrees>{:.7}</LongitudeDegrees></Position>{}<DistanceMeters>{:.2}</DistanceMeters><Extensions><ns3:TPX><ns3:Speed>{:.3}</ns3:Speed></ns3:TPX></Extensions></Trackpoint>",
+                time,
+                point.lat(),
+                point.lon(),
+                altitude,
+                distance,
+                point.speed() / 3.6
+            ));
+        }
+
+        let (start_time, start_point, start_distance) = match lap_start {
+            Some(lap_start) => lap_start,
+            None => continue,
+        };
+        let total_time = match (start_point.timestamp(), lap_end.map(|p| p.timestamp())) {
+            (Ok(start), Some(Ok(end))) => (end - start).num_seconds().max(0),
+            _ => 0,
+        };
+        let notes = child.sources_in(&range).join(", ");
+        laps.push(format!(
+            "<Lap StartTime=\"{}\"><TotalTimeSeconds>{}</TotalTimeSeconds><DistanceMeters>{:.2}</DistanceMeters><MaximumSpeed>{:.3}</MaximumSpeed><Calories>0</Calories><Intensity>Active</Intensity><TriggerMethod>Manual</TriggerMethod><Track>{}</Track><Notes>{}</Notes></Lap>\n",
+            start_time,
+            total_time,
+            distance - start_distance,
+            max_speed,
+            trackpoints.join(""),
+            escape_xml(&notes)
+        ));
+    }
+    if laps.is_empty() {
+        return None;
+    }
+
+    let notes = match child.name() {
+        Some(name) => format!("<Notes>{}</Notes>", escape_xml(name)),
+        None => String::new(),
+    };
+    Some(format!(
+        "<Activity Sport=\"Other\"><Id>{}</Id>\n{}{}<Creator xsi:type=\"Device_t\"><Name>zdr055_gpx</Name><UnitId>0</UnitId><ProductID>0</ProductID><Version><VersionMajor>0</VersionMajor><VersionMinor>1</VersionMinor></Version></Creator></Activity>\n",
+        id,
+        laps.join(""),
+        notes
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpx::reader::{XMLReader, XMLToken};
+
+    // (親要素, 要素, テキスト) の一覧
+    fn texts(s: &str) -> Vec<(String, String, String)> {
+        let mut reader = XMLReader::new(s);
+        let mut path: Vec<String> = Vec::new();
+        let mut texts = Vec::new();
+        while let Some(token) = reader.next_token().unwrap() {
+            match token {
+                XMLToken::Start {
+                    name, self_closing, ..
+                } => {
+                    if !self_closing {
+                        path.push(name);
+                    }
+                }
+                XMLToken::End { .. } => {
+                    path.pop();
+                }
+                XMLToken::Text(text) => texts.push((
+                    path[path.len() - 2].clone(),
+                    path[path.len() - 1].clone(),
+                    text,
+                )),
+            }
+        }
+        texts
+    }
+
+    fn values<'a>(texts: &'a [(String, String, String)], parent: &str, name: &str) -> Vec<&'a str> {
+        texts
+            .iter()
+            .filter(|(p, n, _)| p == parent && n == name)
+            .map(|(_, _, text)| text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn writes_lap_per_clip() {
+        let mut log = GPXTrackLog::new();
+        log.set_name("trip");
+        for (clip, source) in ["a.AVI", "b.AVI"].iter().enumerate() {
+            log.add_source(source);
+            for i in 0..3 {
+                let n = clip * 3 + i;
+                log.push(GPXTrackPoint::new(
+                    35.0 + n as f64 * 0.001,
+                    139.0,
+                    None,
+                    36.0,
+                    format!("2025-07-20 12:{:02}:{:02}", clip * 10, i),
+                ));
+            }
+        }
+        let step = log.points()[0].distance_to(&log.points()[1]);
+
+        let s = TCX::new(vec![log]).to_str();
+        let texts = texts(&s);
+        assert_eq!(
+            values(&texts, "Activity", "Id"),
+            vec!["2025-07-20T03:00:00Z"]
+        );
+        assert_eq!(values(&texts, "Activity", "Notes"), vec!["trip"]);
+        assert_eq!(values(&texts, "Lap", "Notes"), vec!["a.AVI", "b.AVI"]);
+        assert_eq!(values(&texts, "Lap", "TotalTimeSeconds"), vec!["2", "2"]);
+        assert_eq!(s.matches("<Lap StartTime=\"2025-07-20T03:").count(), 2);
+
+        // Trackpoint の距離はクリップをまたいで累積し、 Lap の距離はその Lap 内の分だけ
+        let lap_distances = values(&texts, "Lap", "DistanceMeters");
+        assert_eq!(lap_distances, vec![format!("{:.2}", step * 2.0); 2]);
+        let distances = values(&texts, "Trackpoint", "DistanceMeters");
+        let expected = (0..6)
+            .map(|i| format!("{:.2}", step * i as f64))
+            .collect::<Vec<_>>();
+        assert_eq!(distances, expected);
+        assert_eq!(values(&texts, "TPX", "Speed"), vec!["10.000"; 6]);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::gpx::track_log::GPXTrackLog;
+use crate::gpx::track_point::GPXTrackPoint;
+use crate::stats::STOPPED_SPEED;
+use crate::zdr055::filename::{ClipMode, ClipName, RecordingPosition};
+
+// 走行中の電圧からこれ以上下がっていたらエンジンを切ったとみなす (V)
+const VOLTAGE_DROP: f64 = 1.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum TripMode {
+    // クリップ間の時間だけで分ける (--merge-threshold)
+    Time,
+    // 停止時間とエンジンを切った形跡で分ける
+    Activity,
+}
+
+// 末尾 (reverse なら先頭) から続く停止中の時間 (秒)
+fn stationary_seconds(log: &GPXTrackLog, reverse: bool) -> f64 {
+    let points = log.points();
+    let edge = if reverse {
+        points.first()
+    } else {
+        points.last()
+    };
+    let Some(Ok(edge_time)) = edge.map(|point| point.timestamp()) else {
+        return 0.0;
+    };
+    let moving = if reverse {
+        points.iter().find(|point| point.speed() >= STOPPED_SPEED)
+    } else {
+        points
+            .iter()
+            .rev()
+            .find(|point| point.speed() >= STOPPED_SPEED)
+    };
+    let boundary = match moving.map(|point| point.timestamp()) {
+        Some(Ok(time)) => time,
+        Some(Err(_)) => return 0.0,
+        // ずっと止まっている
+        None => match (points.first(), points.last()) {
+            (Some(first), Some(last)) => match (first.timestamp(), last.timestamp()) {
+                (Ok(first), Ok(last)) => {
+                    return (last - first).num_milliseconds() as f64 / 1000.0;
+                }
+                _ => return 0.0,
+            },
+            _ => return 0.0,
+        },
+    };
+    (edge_time - boundary).num_milliseconds().abs() as f64 / 1000.0
+}
+
+// last から first まで、 gap の間に max_speed (km/h) で移動できる距離に max_distance (m) を足しても
+// 届かないほど離れていれば、その説明を返す。別の車のカードが混ざっている場合など
+pub(crate) fn implausible_jump(
+    last: &GPXTrackPoint,
+    first: &GPXTrackPoint,
+    gap: std::time::Duration,
+    max_distance: f64,
+    max_speed: f64,
+) -> Option<String> {
+    let distance = last.distance_to(first);
+    let allowed = max_distance + max_speed / 3.6 * gap.as_secs_f64();
+    if distance > allowed {
+        Some(format!(
+            "jumped {:.1} km in {} (allowed {:.1} km)",
+            distance / 1000.0,
+            humantime::format_duration(std::time::Duration::from_secs(gap.as_secs())),
+            allowed / 1000.0
+        ))
+    } else {
+        None
+    }
+}
+
+// 連続したクリップを1つのトリップにまとめるかどうかを決める
+#[derive(Debug, Clone)]
+pub(crate) struct TripDetector {
+    // これ以上止まっていたら別のトリップとする
+    stop_duration: std::time::Duration,
+}
+
+impl TripDetector {
+    pub(crate) fn new(stop_duration: std::time::Duration) -> Self {
+        TripDetector { stop_duration }
+    }
+
+    // 駐車監視で記録したクリップ
+    pub(crate) fn is_parking_clip(&self, path: &std::path::Path) -> bool {
+        ClipName::from_path(path).is_some_and(|name| name.mode() == Some(ClipMode::Parking))
+    }
+
+    // 別のトリップにする場合はその理由を返す。
+    // parked は間に駐車監視のクリップがあったかどうか
+    pub(crate) fn split_reason(
+        &self,
+        previous_path: &std::path::Path,
+        previous: &GPXTrackLog,
+        next_path: &std::path::Path,
+        next: &GPXTrackLog,
+        parked: bool,
+    ) -> Option<String> {
+        let (last, first) = (previous.last()?, next.first()?);
+        let (last_time, first_time) = (last.timestamp().ok()?, first.timestamp().ok()?);
+        let gap = ((first_time - last_time).num_milliseconds() as f64 / 1000.0).max(0.0);
+        let stop_duration = self.stop_duration.as_secs_f64();
+        if gap >= stop_duration {
+            return Some(format!(
+                "recorder was off for {}",
+                humantime::format_duration(std::time::Duration::from_secs(gap as u64))
+            ));
+        }
+
+        let mut evidence = Vec::new();
+        if parked {
+            evidence.push("parking recording".to_string());
+        }
+        let position = |path| ClipName::from_path(path).and_then(|name| name.position());
+        if position(previous_path) == Some(RecordingPosition::End) {
+            evidence.push("recording ended".to_string());
+        }
+        if position(next_path) == Some(RecordingPosition::Start) {
+            evidence.push("recording started".to_string());
+        }
+        let max_voltage = previous
+            .points()
+            .iter()
+            .filter_map(|point| point.voltage())
+            .fold(f64::NAN, f64::max);
+        if let Some(voltage) = last.voltage() {
+            if max_voltage - voltage >= VOLTAGE_DROP {
+                evidence.push(format!("voltage dropped to {:.1} V", voltage));
+            }
+        }
+        if evidence.is_empty() {
+            return None;
+        }
+
+        let stopped = stationary_seconds(previous, false) + gap + stationary_seconds(next, true);
+        if stopped >= stop_duration {
+            Some(format!(
+                "stopped for {} ({})",
+                humantime::format_duration(std::time::Duration::from_secs(stopped as u64)),
+                evidence.join(", ")
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpx::track_point::GPXTrackPoint;
+    use std::path::Path;
+
+    // start 秒から seconds 秒間、moving 秒だけ走ってから止まるログ
+    fn log(start: usize, seconds: usize, moving: usize, voltage: f64) -> GPXTrackLog {
+        let mut log = GPXTrackLog::new();
+        for i in 0..seconds {
+            let time = start + i;
+            let mut point = GPXTrackPoint::new(
+                35.0,
+                139.0,
+                None,
+                if i < moving { 40.0 } else { 0.0 },
+                format!(
+                    "2025-07-20 {:02}:{:02}:{:02}",
+                    time / 3600,
+                    time / 60 % 60,
+                    time % 60
+                ),
+            );
+            point.set_voltage(Some(if i < moving { 14.2 } else { voltage }));
+            log.push(point);
+        }
+        log
+    }
+
+    #[test]
+    fn needs_evidence_of_ignition_off() {
+        let detector = TripDetector::new(std::time::Duration::from_secs(900));
+        let previous = log(36000, 1800, 60, 14.2);
+        let next = log(37800, 60, 0, 14.2);
+        // 渋滞などで止まっていても録画が続いていれば同じトリップ
+        let (a, b) = (
+            Path::new("20250720_100000_F_M_Nor.AVI"),
+            Path::new("20250720_103000_F_M_Nor.AVI"),
+        );
+        assert!(detector
+            .split_reason(a, &previous, b, &next, false)
+            .is_none());
+        assert!(detector
+            .split_reason(a, &previous, b, &next, true)
+            .is_some());
+
+        // エンジンを切った形跡があっても 5 分程度なら同じトリップ
+        let previous = log(36000, 120, 100, 12.4);
+        let next = log(36420, 60, 10, 14.2);
+        let b = Path::new("20250720_100700_F_S_Nor.AVI");
+        assert!(detector
+            .split_reason(a, &previous, b, &next, false)
+            .is_none());
+    }
+
+    #[test]
+    fn splits_after_long_stop() {
+        let detector = TripDetector::new(std::time::Duration::from_secs(900));
+        let previous = log(36000, 1200, 60, 12.4);
+        let next = log(37200, 600, 500, 14.2);
+        let (a, b) = (
+            Path::new("20250720_100000_F_M_Nor.AVI"),
+            Path::new("20250720_102000_F_M_Nor.AVI"),
+        );
+        let reason = detector
+            .split_reason(a, &previous, b, &next, false)
+            .unwrap();
+        assert!(reason.contains("voltage dropped to 12.4 V"), "{}", reason);
+        // 録画が途切れていた時間が長ければ形跡が無くても分ける
+        let next = log(40000, 60, 60, 14.2);
+        assert!(detector
+            .split_reason(a, &previous, b, &next, false)
+            .is_some());
+        assert!(detector.is_parking_clip(Path::new("20250720_101000_F_S_Park.AVI")));
+        assert!(!detector.is_parking_clip(b));
+    }
+
+    #[test]
+    fn detects_teleport_between_clips() {
+        let last = GPXTrackPoint::new(35.68, 139.76, None, 0.0, String::new());
+        // 大阪まで約 400 km
+        let first = GPXTrackPoint::new(34.70, 135.50, None, 0.0, String::new());
+        let minute = std::time::Duration::from_secs(60);
+        assert!(implausible_jump(&last, &first, minute, 1000.0, 150.0).is_some());
+        assert!(implausible_jump(&last, &first, minute * 240, 1000.0, 150.0).is_none());
+        let near = GPXTrackPoint::new(35.685, 139.76, None, 0.0, String::new());
+        assert!(implausible_jump(&last, &near, minute, 1000.0, 150.0).is_none());
+    }
+}
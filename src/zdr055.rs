@@ -1,3 +1,6 @@
+pub(crate) mod filename;
+pub(crate) mod pair;
+
 use std::{
     fs::File,
     io::{Read, Seek, SeekFrom},
@@ -7,7 +10,7 @@ use std::{
 
 use crate::{
     container::avi::{AVI, FILESIZE_FIELD_SIZE, IDX1_FOURCC, IDX1_INDEX_ENTRY_SIZE},
-    gpx::track_point::GPXTrackPoint,
+    gpx::{metadata::GPXDevice, track_point::GPXTrackPoint},
 };
 
 const GPS_DATA_CHUNK_ID: &[u8; 4] = b"02tx";
@@ -43,9 +46,7 @@ impl ZDR055MediaData {
         if !self.filename.is_file() {
             return Err("Path is not a file".to_string());
         }
-        if let Err(e) = self.check_filename() {
-            return Err(e);
-        }
+        self.check_filename()?;
 
         let mut file =
             File::open(&self.filename).map_err(|e| format!("Failed to open file: {}", e))?;
@@ -211,7 +212,7 @@ impl FromStr for ZDR055PositionData {
 
         // --- 6. 速度 ---
         s = s.trim_start();
-        let (speed_str, mut s) = s.split_once("km/h").ok_or("Invalid format for speed")?;
+        let (speed_str, s) = s.split_once("km/h").ok_or("Invalid format for speed")?;
         let speed = speed_str
             .parse::<f64>()
             .map_err(|_| format!("Failed to parse speed: {}", speed_str))?;
@@ -292,21 +293,58 @@ impl FromStr for ZDR055PositionData {
 
 impl ZDR055PositionData {
     pub(crate) fn to_gpx_point(&self) -> GPXTrackPoint {
-        GPXTrackPoint::new(
+        let mut point = GPXTrackPoint::new(
             self.latitude,
             self.longitude,
-            0.0,
+            None,
             self.speed,
             self.timestamp.clone(),
-        )
+        );
+        if !self.event_type.is_empty() {
+            point.set_event(Some(self.event_type.clone()));
+        }
+        point.set_acceleration(Some([self.x_accel, self.y_accel, self.z_accel]));
+        point.set_voltage(Some(self.supply_voltage));
+        point
+    }
+
+    pub(crate) fn to_gpx_device(&self) -> GPXDevice {
+        GPXDevice::new(&self.device, &self.firmware_version.to_string())
+    }
+
+    // すべてのフィールドを (フィールド名, 値) の組で返す。
+    // 値はログの行と同じ小数点以下の桁数で文字列にする (1.00 が 1 にならないように)
+    pub(crate) fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("device", self.device.clone()),
+            ("timestamp", self.timestamp.clone()),
+            ("x_accel", format!("{:.2}", self.x_accel)),
+            ("y_accel", format!("{:.2}", self.y_accel)),
+            ("z_accel", format!("{:.2}", self.z_accel)),
+            ("unknown_field_t", self.unknown_field_t.clone()),
+            ("supply_voltage", format!("{:.1}", self.supply_voltage)),
+            ("event_type", self.event_type.clone()),
+            ("latitude", format!("{:.6}", self.latitude)),
+            ("longitude", format!("{:.6}", self.longitude)),
+            ("speed", format!("{:.1}", self.speed)),
+            ("e_value", self.e_value.to_string()),
+            ("m_value", self.m_value.to_string()),
+            ("em_value", self.em_value.to_string()),
+            ("sa_value", self.sa_value.to_string()),
+            ("firmware_version", self.firmware_version.to_string()),
+            ("s_value", self.s_value.to_string()),
+            ("unknown_field_tail", self.unknown_field_tail.clone()),
+            ("is_valid", self.is_valid.to_string()),
+        ]
     }
 
     pub(crate) fn is_valid(&self) -> bool {
         self.is_valid
     }
 
-    pub(crate) fn has_same_timestamp(&self, other: &ZDR055PositionData) -> bool {
-        self.timestamp == other.timestamp
+    // ドラレコの時計での記録時刻 ("YYYY-MM-DD HH:MM:SS")
+    pub(crate) fn timestamp(&self) -> &str {
+        &self.timestamp
     }
 
     pub(crate) fn has_same_position(&self, other: &ZDR055PositionData) -> bool {
@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum Camera {
+    Front,
+    Rear,
+}
+
+impl Camera {
+    pub(crate) fn letter(&self) -> &'static str {
+        match self {
+            Camera::Front => "F",
+            Camera::Rear => "R",
+        }
+    }
+}
+
+// 連続録画の中での位置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RecordingPosition {
+    Start,
+    Middle,
+    End,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum ClipMode {
+    Normal,
+    Event,
+    Parking,
+    Manual,
+}
+
+impl ClipMode {
+    fn from_tag(tag: &str) -> Option<Self> {
+        let tag = tag.to_ascii_lowercase();
+        if tag.starts_with("nor") {
+            Some(ClipMode::Normal)
+        } else if tag.starts_with("ev") {
+            Some(ClipMode::Event)
+        } else if tag.starts_with("par") || tag.starts_with("prk") {
+            Some(ClipMode::Parking)
+        } else if tag.starts_with("man") {
+            Some(ClipMode::Manual)
+        } else {
+            None
+        }
+    }
+}
+
+// 20250720_124810_F_S_Nor_Res.AVI のようなファイル名から読み取った情報。
+// 日時以外は順番が違っていたり欠けていたりしても読めたものだけを埋め、分からない部分は flags に入れる
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ClipName {
+    start: NaiveDateTime,
+    camera: Option<Camera>,
+    position: Option<RecordingPosition>,
+    mode: Option<ClipMode>,
+    flags: Vec<String>,
+}
+
+impl ClipName {
+    pub(crate) fn parse(stem: &str) -> Option<Self> {
+        let mut fields = stem.split('_');
+        let date = fields.next()?;
+        let time = fields.next()?;
+        if date.len() != 8 || time.len() != 6 {
+            return None;
+        }
+        let start =
+            NaiveDateTime::parse_from_str(&format!("{}{}", date, time), "%Y%m%d%H%M%S").ok()?;
+
+        let mut name = ClipName {
+            start,
+            camera: None,
+            position: None,
+            mode: None,
+            flags: Vec::new(),
+        };
+        for field in fields {
+            match field {
+                "F" if name.camera.is_none() => name.camera = Some(Camera::Front),
+                "R" if name.camera.is_none() => name.camera = Some(Camera::Rear),
+                "S" if name.position.is_none() => name.position = Some(RecordingPosition::Start),
+                "M" if name.position.is_none() => name.position = Some(RecordingPosition::Middle),
+                "E" if name.position.is_none() => name.position = Some(RecordingPosition::End),
+                _ => match ClipMode::from_tag(field) {
+                    Some(mode) if name.mode.is_none() => name.mode = Some(mode),
+                    _ => name.flags.push(field.to_string()),
+                },
+            }
+        }
+        Some(name)
+    }
+
+    pub(crate) fn from_path(path: &std::path::Path) -> Option<Self> {
+        ClipName::parse(path.file_stem()?.to_str()?)
+    }
+
+    // ファイル名の日時 (ドラレコの時計の現地時刻)
+    pub(crate) fn start(&self) -> NaiveDateTime {
+        self.start
+    }
+
+    pub(crate) fn camera(&self) -> Option<Camera> {
+        self.camera
+    }
+
+    pub(crate) fn position(&self) -> Option<RecordingPosition> {
+        self.position
+    }
+
+    pub(crate) fn mode(&self) -> Option<ClipMode> {
+        self.mode
+    }
+
+    // 複数のクリップをまとめたトリップの名前。クリップごとに変わる部分は含めない
+    pub(crate) fn trip_stem(&self) -> String {
+        let mut stem = self.start.format("%Y%m%d_%H%M%S").to_string();
+        if let Some(camera) = self.camera {
+            stem.push('_');
+            stem.push_str(camera.letter());
+        }
+        stem
+    }
+}
+
+// --camera と --mode による絞り込み。空なら絞り込まない
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClipFilter {
+    cameras: Vec<Camera>,
+    modes: Vec<ClipMode>,
+    // 前後のカメラの組のうち読まない方など、個別に除くファイル
+    excluded: Arc<HashSet<PathBuf>>,
+}
+
+impl ClipFilter {
+    pub(crate) fn new(cameras: Vec<Camera>, modes: Vec<ClipMode>) -> Self {
+        ClipFilter {
+            cameras,
+            modes,
+            excluded: Arc::new(HashSet::new()),
+        }
+    }
+
+    pub(crate) fn exclude(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        let mut excluded = (*self.excluded).clone();
+        excluded.extend(paths);
+        self.excluded = Arc::new(excluded);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.cameras.is_empty() && self.modes.is_empty()
+    }
+
+    // ファイル名を読めない場合は条件を満たすか分からないので含めない
+    pub(crate) fn matches(&self, path: &std::path::Path) -> bool {
+        if self.excluded.contains(path) {
+            return false;
+        }
+        if self.is_empty() {
+            return true;
+        }
+        let Some(name) = ClipName::from_path(path) else {
+            return false;
+        };
+        let camera = self.cameras.is_empty()
+            || name
+                .camera()
+                .is_some_and(|camera| self.cameras.contains(&camera));
+        let mode =
+            self.modes.is_empty() || name.mode().is_some_and(|mode| self.modes.contains(&mode));
+        camera && mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_clip_name() {
+        let name = ClipName::parse("20250720_124810_F_S_Nor_Res").unwrap();
+        assert_eq!(
+            name.start().format("%Y-%m-%d %H:%M:%S").to_string(),
+            "2025-07-20 12:48:10"
+        );
+        assert_eq!(name.camera(), Some(Camera::Front));
+        assert_eq!(name.position(), Some(RecordingPosition::Start));
+        assert_eq!(name.mode(), Some(ClipMode::Normal));
+        assert_eq!(name.flags, ["Res".to_string()]);
+        assert_eq!(name.trip_stem(), "20250720_124810_F");
+    }
+
+    #[test]
+    fn tolerates_unknown_fields() {
+        let name = ClipName::parse("20250720_235010_R_Park_X2_E").unwrap();
+        assert_eq!(name.camera(), Some(Camera::Rear));
+        assert_eq!(name.mode(), Some(ClipMode::Parking));
+        assert_eq!(name.position(), Some(RecordingPosition::End));
+        assert_eq!(name.flags, ["X2".to_string()]);
+
+        let name = ClipName::parse("20250720_235010").unwrap();
+        assert_eq!(name.camera(), None);
+        assert_eq!(name.trip_stem(), "20250720_235010");
+
+        assert!(ClipName::parse("FILE0001").is_none());
+        assert!(ClipName::parse("20251340_124810_F").is_none());
+    }
+
+    #[test]
+    fn filters_by_camera_and_mode() {
+        let path = std::path::Path::new("sd/Front/20250720_124810_F_S_Evt.AVI");
+        assert!(ClipFilter::default().matches(path));
+        assert!(ClipFilter::new(vec![Camera::Front], vec![]).matches(path));
+        assert!(!ClipFilter::new(vec![Camera::Rear], vec![]).matches(path));
+        assert!(ClipFilter::new(vec![], vec![ClipMode::Event, ClipMode::Manual]).matches(path));
+        assert!(!ClipFilter::new(vec![], vec![ClipMode::Normal]).matches(path));
+        assert!(!ClipFilter::new(vec![Camera::Front], vec![])
+            .matches(std::path::Path::new("FILE0001.AVI")));
+        let mut filter = ClipFilter::default();
+        filter.exclude([path.to_path_buf()]);
+        assert!(!filter.matches(path));
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+
+use super::filename::{Camera, ClipName};
+use super::ZDR055PositionData;
+
+// 前後のカメラのファイル名の日時がこれ以内なら同じ録画とみなす (秒)
+const MAX_START_DIFFERENCE: i64 = 2;
+
+// 同じ時間を前後のカメラで記録したクリップの組 (前, 後) を探す。
+// 録画の種類と連続録画の中での位置が同じで、ファイル名の日時が近いものを組にする
+pub(crate) fn find_camera_pairs(
+    paths: &[std::path::PathBuf],
+) -> Vec<(std::path::PathBuf, std::path::PathBuf)> {
+    let named = paths
+        .iter()
+        .filter_map(|path| ClipName::from_path(path).map(|name| (path, name)))
+        .collect::<Vec<_>>();
+    let mut rears = named
+        .iter()
+        .filter(|(_, name)| name.camera() == Some(Camera::Rear))
+        .map(|(path, name)| (*path, name, false))
+        .collect::<Vec<_>>();
+
+    let mut pairs = Vec::new();
+    for (front_path, front) in named
+        .iter()
+        .filter(|(_, name)| name.camera() == Some(Camera::Front))
+    {
+        let rear = rears
+            .iter_mut()
+            .filter(|(_, rear, used)| {
+                !used
+                    && rear.mode() == front.mode()
+                    && rear.position() == front.position()
+                    && (rear.start() - front.start()).num_seconds().abs() <= MAX_START_DIFFERENCE
+            })
+            .min_by_key(|(_, rear, _)| (rear.start() - front.start()).num_seconds().abs());
+        if let Some((rear_path, _, used)) = rear {
+            *used = true;
+            pairs.push(((*front_path).clone(), (*rear_path).clone()));
+        }
+    }
+    pairs
+}
+
+// 前後のカメラのログを時刻で突き合わせ、違いの説明を返す。同じなら空
+pub(crate) fn compare_telemetry(
+    front: &[(usize, ZDR055PositionData)],
+    rear: &[(usize, ZDR055PositionData)],
+) -> Vec<String> {
+    let by_time = |samples: &[(usize, ZDR055PositionData)]| {
+        samples
+            .iter()
+            .map(|(_, sample)| (sample.timestamp().to_string(), sample.fields()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let (front, rear) = (by_time(front), by_time(rear));
+
+    let mut differences = Vec::new();
+    let only_front = front
+        .keys()
+        .filter(|time| !rear.contains_key(*time))
+        .count();
+    if only_front > 0 {
+        differences.push(format!("{} samples only in front", only_front));
+    }
+    let only_rear = rear
+        .keys()
+        .filter(|time| !front.contains_key(*time))
+        .count();
+    if only_rear > 0 {
+        differences.push(format!("{} samples only in rear", only_rear));
+    }
+
+    let mut differing = 0;
+    let mut fields = Vec::new();
+    for (time, front_fields) in &front {
+        let Some(rear_fields) = rear.get(time) else {
+            continue;
+        };
+        let mut differs = false;
+        for ((field, front_value), (_, rear_value)) in front_fields.iter().zip(rear_fields) {
+            if front_value != rear_value {
+                differs = true;
+                if !fields.contains(field) {
+                    fields.push(*field);
+                }
+            }
+        }
+        differing += differs as usize;
+    }
+    if differing > 0 {
+        differences.push(format!(
+            "{} samples differ in {}",
+            differing,
+            fields.join(", ")
+        ));
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    fn sample(time: &str, speed: f64) -> (usize, ZDR055PositionData) {
+        let line = format!(
+            "ZDR055:2025-07-20 {} X:0.01 Y:-0.02 Z:1.00 T:0 12.3V N 35.680000 N 139.760000 E {:.1}km/h E:0 M:1 EM:0 SA:2 V:13 S:120k 0A",
+            time, speed
+        );
+        (0, ZDR055PositionData::from_str(&line).unwrap())
+    }
+
+    #[test]
+    fn pairs_front_and_rear_clips() {
+        let paths = [
+            "Front/20250720_124810_F_S_Nor.AVI",
+            "Front/20250720_124910_F_M_Nor.AVI",
+            "Front/20250720_124910_F_M_Evt.AVI",
+            "Rear/20250720_124811_R_S_Nor.AVI",
+            "Rear/20250720_124910_R_M_Nor.AVI",
+            "Rear/20250720_130000_R_M_Nor.AVI",
+        ]
+        .map(PathBuf::from);
+        let pairs = find_camera_pairs(&paths);
+        assert_eq!(
+            pairs,
+            vec![
+                (paths[0].clone(), paths[3].clone()),
+                (paths[1].clone(), paths[4].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_telemetry_differences() {
+        let front = vec![
+            sample("12:48:10", 40.0),
+            sample("12:48:11", 41.0),
+            sample("12:48:12", 42.0),
+        ];
+        assert!(compare_telemetry(&front, &front).is_empty());
+        let rear = vec![sample("12:48:10", 40.0), sample("12:48:11", 45.0)];
+        assert_eq!(
+            compare_telemetry(&front, &rear),
+            vec![
+                "1 samples only in front".to_string(),
+                "1 samples differ in speed".to_string()
+            ]
+        );
+    }
+}
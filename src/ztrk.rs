@@ -0,0 +1,420 @@
+use chrono::DateTime;
+use chrono_tz::Asia::Tokyo;
+
+use crate::gpx::metadata::GPXDevice;
+use crate::gpx::track_log::GPXTrackLog;
+use crate::gpx::track_point::GPXTrackPoint;
+
+// 保存用の独自バイナリ形式。各値を直前の点との差分にして可変長整数で詰める
+//
+// ファイル: "ZTRK" | バージョン (u8) | 緯度経度の小数点以下の桁数 (u8) | トラック数 | トラック...
+// トラック: 名前 | 機種 | 元ファイル数 | (元ファイル名 | 開始インデックス)... | 点の数 | 点...
+// 点: フラグ (u8) | [時刻 (秒)] | 緯度 | 経度 | 速度 (0.01 km/h) | [標高 (0.01 m)]
+//     | [加速度 X, Y, Z (0.01 G)] | [電圧 (0.01 V)] | [イベント種別]
+// 補間で作った点はフラグの FLAG_SYNTHESIZED だけで表し、値は持たない
+// 整数は LEB128、符号付きのものは zigzag 符号化してから LEB128、文字列は長さ + UTF-8
+const MAGIC: &[u8; 4] = b"ZTRK";
+const VERSION: u8 = 1;
+
+const FLAG_TIME: u8 = 0x01;
+const FLAG_ELE: u8 = 0x02;
+const FLAG_ACCELERATION: u8 = 0x04;
+const FLAG_VOLTAGE: u8 = 0x08;
+const FLAG_EVENT: u8 = 0x10;
+const FLAG_SYNTHESIZED: u8 = 0x20;
+
+// 座標以外の値の倍率
+const VALUE_FACTOR: f64 = 100.0;
+
+fn write_uvarint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_svarint(buffer: &mut Vec<u8>, value: i64) {
+    write_uvarint(buffer, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    write_uvarint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+struct ZTRKReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ZTRKReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ZTRKReader { data, position: 0 }
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or("Unexpected end of ZTRK data")?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or("Unexpected end of ZTRK data")?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_uvarint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err("Invalid variable-length integer in ZTRK data".to_string())
+    }
+
+    fn read_svarint(&mut self) -> Result<i64, String> {
+        let value = self.read_uvarint()?;
+        Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+
+    fn read_len(&mut self) -> Result<usize, String> {
+        let len = self.read_uvarint()? as usize;
+        // 壊れたファイルで巨大な領域を確保しないよう、残りのバイト数を上限とする
+        if len > self.data.len() - self.position {
+            return Err("Invalid length in ZTRK data".to_string());
+        }
+        Ok(len)
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        let len = self.read_len()?;
+        let bytes = self.read_bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| format!("Invalid string in ZTRK data: {}", e))
+    }
+}
+
+// 差分を取るための直前の値
+#[derive(Default)]
+struct PreviousValues {
+    time: i64,
+    lat: i64,
+    lon: i64,
+    speed: i64,
+    ele: i64,
+    acceleration: [i64; 3],
+    voltage: i64,
+}
+
+fn scale(value: f64, factor: f64) -> i64 {
+    (value * factor).round() as i64
+}
+
+#[derive(Debug)]
+pub(crate) struct ZTRK {
+    children: Vec<GPXTrackLog>,
+    precision: u32,
+}
+
+impl ZTRK {
+    pub(crate) fn new(children: Vec<GPXTrackLog>) -> Self {
+        ZTRK {
+            children,
+            precision: 7,
+        }
+    }
+
+    // 緯度経度の小数点以下の桁数
+    pub(crate) fn set_precision(&mut self, precision: u32) {
+        self.precision = precision;
+    }
+
+    pub(crate) fn into_tracks(self) -> Vec<GPXTrackLog> {
+        self.children
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let factor = 10f64.powi(self.precision as i32);
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(MAGIC);
+        buffer.push(VERSION);
+        buffer.push(self.precision as u8);
+        write_uvarint(&mut buffer, self.children.len() as u64);
+
+        for child in &self.children {
+            write_string(&mut buffer, child.name().unwrap_or_default());
+            match child.device() {
+                Some(device) => {
+                    buffer.push(1);
+                    write_string(&mut buffer, device.name());
+                    write_string(&mut buffer, device.firmware_version());
+                }
+                None => buffer.push(0),
+            }
+            let sources = child.source_ranges();
+            write_uvarint(&mut buffer, sources.len() as u64);
+            for (source, range) in sources {
+                write_string(&mut buffer, source);
+                write_uvarint(&mut buffer, range.start as u64);
+            }
+
+            write_uvarint(&mut buffer, child.points().len() as u64);
+            let mut previous = PreviousValues::default();
+            for point in child.points() {
+                let timestamp = point.timestamp().ok();
+                let mut flags = 0;
+                if timestamp.is_some() {
+                    flags |= FLAG_TIME;
+                }
+                if point.ele().is_some() {
+                    flags |= FLAG_ELE;
+                }
+                if point.acceleration().is_some() {
+                    flags |= FLAG_ACCELERATION;
+                }
+                if point.voltage().is_some() {
+                    flags |= FLAG_VOLTAGE;
+                }
+                if point.event().is_some() {
+                    flags |= FLAG_EVENT;
+                }
+                if point.is_synthesized() {
+                    flags |= FLAG_SYNTHESIZED;
+                }
+                buffer.push(flags);
+
+                if let Some(timestamp) = timestamp {
+                    let time = timestamp.timestamp();
+                    write_svarint(&mut buffer, time - previous.time);
+                    previous.time = time;
+                }
+                let (lat, lon) = (scale(point.lat(), factor), scale(point.lon(), factor));
+                write_svarint(&mut buffer, lat - previous.lat);
+                write_svarint(&mut buffer, lon - previous.lon);
+                (previous.lat, previous.lon) = (lat, lon);
+                let speed = scale(point.speed(), VALUE_FACTOR);
+                write_svarint(&mut buffer, speed - previous.speed);
+                previous.speed = speed;
+                if let Some(ele) = point.ele() {
+                    let ele = scale(ele, VALUE_FACTOR);
+                    write_svarint(&mut buffer, ele - previous.ele);
+                    previous.ele = ele;
+                }
+                if let Some(acceleration) = point.acceleration() {
+                    for (axis, value) in acceleration.iter().enumerate() {
+                        let value = scale(*value, VALUE_FACTOR);
+                        write_svarint(&mut buffer, value - previous.acceleration[axis]);
+                        previous.acceleration[axis] = value;
+                    }
+                }
+                if let Some(voltage) = point.voltage() {
+                    let voltage = scale(voltage, VALUE_FACTOR);
+                    write_svarint(&mut buffer, voltage - previous.voltage);
+                    previous.voltage = voltage;
+                }
+                if let Some(event) = point.event() {
+                    write_string(&mut buffer, event);
+                }
+            }
+        }
+        buffer
+    }
+
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let mut reader = ZTRKReader::new(data);
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err("Not a ZTRK file".to_string());
+        }
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(format!("Unsupported ZTRK version: {}", version));
+        }
+        let precision = reader.read_u8()? as u32;
+        let factor = 10f64.powi(precision as i32);
+
+        let mut children = Vec::new();
+        for _ in 0..reader.read_len()? {
+            let mut log = GPXTrackLog::new();
+            let name = reader.read_string()?;
+            if !name.is_empty() {
+                log.set_name(&name);
+            }
+            if reader.read_u8()? != 0 {
+                let device_name = reader.read_string()?;
+                let firmware_version = reader.read_string()?;
+                log.set_device(GPXDevice::new(&device_name, &firmware_version));
+            }
+            let mut sources = Vec::new();
+            for _ in 0..reader.read_len()? {
+                let source = reader.read_string()?;
+                let start = reader.read_uvarint()? as usize;
+                sources.push((source, start));
+            }
+            let mut sources = sources.into_iter().peekable();
+
+            let point_count = reader.read_len()?;
+            let mut previous = PreviousValues::default();
+            for index in 0..point_count {
+                while let Some((source, _)) = sources.next_if(|(_, start)| *start <= index) {
+                    log.add_source(&source);
+                }
+
+                let flags = reader.read_u8()?;
+                let mut time = String::new();
+                if flags & FLAG_TIME != 0 {
+                    previous.time += reader.read_svarint()?;
+                    let timestamp = DateTime::from_timestamp(previous.time, 0)
+                        .ok_or("Invalid timestamp in ZTRK data")?;
+                    time = timestamp
+                        .with_timezone(&Tokyo)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string();
+                }
+                previous.lat += reader.read_svarint()?;
+                previous.lon += reader.read_svarint()?;
+                previous.speed += reader.read_svarint()?;
+                let mut ele = None;
+                if flags & FLAG_ELE != 0 {
+                    previous.ele += reader.read_svarint()?;
+                    ele = Some(previous.ele as f64 / VALUE_FACTOR);
+                }
+                let mut point = GPXTrackPoint::new(
+                    previous.lat as f64 / factor,
+                    previous.lon as f64 / factor,
+                    ele,
+                    previous.speed as f64 / VALUE_FACTOR,
+                    time,
+                );
+                if flags & FLAG_ACCELERATION != 0 {
+                    let mut acceleration = [0.0; 3];
+                    for (axis, value) in acceleration.iter_mut().enumerate() {
+                        previous.acceleration[axis] += reader.read_svarint()?;
+                        *value = previous.acceleration[axis] as f64 / VALUE_FACTOR;
+                    }
+                    point.set_acceleration(Some(acceleration));
+                }
+                if flags & FLAG_VOLTAGE != 0 {
+                    previous.voltage += reader.read_svarint()?;
+                    point.set_voltage(Some(previous.voltage as f64 / VALUE_FACTOR));
+                }
+                if flags & FLAG_EVENT != 0 {
+                    point.set_event(Some(reader.read_string()?));
+                }
+                point.set_synthesized(flags & FLAG_SYNTHESIZED != 0);
+                log.push(point);
+            }
+            // 点を1つも取り込まなかった元ファイル
+            for (source, _) in sources {
+                log.add_source(&source);
+            }
+            children.push(log);
+        }
+
+        let mut ztrk = ZTRK::new(children);
+        ztrk.set_precision(precision);
+        Ok(ztrk)
+    }
+
+    pub(crate) fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|e| format!("Failed to write ZTRK file: {}", e))
+    }
+
+    pub(crate) fn load(path: &std::path::Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read ZTRK file: {}", e))?;
+        ZTRK::from_bytes(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log() -> GPXTrackLog {
+        let mut log = GPXTrackLog::new();
+        log.set_name("drive");
+        log.set_device(GPXDevice::new("ZDR055", "12"));
+        log.add_source("a.AVI");
+        for i in 0..40 {
+            if i == 25 {
+                log.add_source("b.AVI");
+            }
+            let mut point = GPXTrackPoint::new(
+                35.6812362 + i as f64 * 0.0001234567,
+                139.7671248 - i as f64 * 0.0000987654,
+                if i < 10 { None } else { Some(12.5 + i as f64) },
+                40.0 + i as f64 * 0.37,
+                format!("2025-07-20 23:59:{:02}", 20 + i),
+            );
+            point.set_acceleration(Some([0.01, -0.02 * i as f64, 1.0]));
+            point.set_voltage(Some(12.3));
+            point.set_event(Some(if i % 10 == 0 { "E" } else { "N" }.to_string()));
+            point.set_synthesized(i % 7 == 3);
+            log.push(point);
+        }
+        log
+    }
+
+    #[test]
+    fn round_trips_track_log() {
+        let log = sample_log();
+        let tracks = ZTRK::from_bytes(&ZTRK::new(vec![log.clone()]).to_bytes())
+            .unwrap()
+            .into_tracks();
+        assert_eq!(tracks.len(), 1);
+        let decoded = &tracks[0];
+        assert_eq!(decoded.name(), Some("drive"));
+        assert_eq!(decoded.device(), log.device());
+        assert_eq!(decoded.source_ranges(), log.source_ranges());
+        assert_eq!(decoded.points().len(), log.points().len());
+        for (decoded, original) in decoded.points().iter().zip(log.points()) {
+            assert!((decoded.lat() - original.lat()).abs() <= 0.5e-7);
+            assert!((decoded.lon() - original.lon()).abs() <= 0.5e-7);
+            assert!((decoded.speed() - original.speed()).abs() < 1e-9);
+            assert_eq!(decoded.timestamp(), original.timestamp());
+            assert_eq!(decoded.ele(), original.ele());
+            let (decoded_acceleration, original_acceleration) = (
+                decoded.acceleration().unwrap(),
+                original.acceleration().unwrap(),
+            );
+            for (decoded, original) in decoded_acceleration.iter().zip(original_acceleration) {
+                assert!((decoded - original).abs() < 1e-9);
+            }
+            assert_eq!(decoded.voltage(), original.voltage());
+            assert_eq!(decoded.event(), original.event());
+            assert_eq!(decoded.is_synthesized(), original.is_synthesized());
+        }
+    }
+
+    #[test]
+    fn precision_limits_coordinate_error() {
+        let log = sample_log();
+        for precision in [4, 5, 6] {
+            let mut ztrk = ZTRK::new(vec![log.clone()]);
+            ztrk.set_precision(precision);
+            let tracks = ZTRK::from_bytes(&ztrk.to_bytes()).unwrap().into_tracks();
+            let tolerance = 0.5 / 10f64.powi(precision as i32) + 1e-12;
+            for (decoded, original) in tracks[0].points().iter().zip(log.points()) {
+                assert!((decoded.lat() - original.lat()).abs() <= tolerance);
+                assert!((decoded.lon() - original.lon()).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let bytes = ZTRK::new(vec![sample_log()]).to_bytes();
+        assert!(ZTRK::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(ZTRK::from_bytes(b"GPX!").is_err());
+    }
+}